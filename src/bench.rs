@@ -0,0 +1,184 @@
+use crate::config::{Args, BenchArgs};
+use anyhow::Result;
+use hotel_broken_image_checker::mock_server::{MockServer, SyntheticLoad};
+use hotel_broken_image_checker::policy::NetworkPolicy;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Check synthetic URLs against the local server with the run's checker settings
+///
+/// Everything but the database goes through the real pipeline (concurrency limits, retry
+/// phases, redirects and classification), so the numbers can be compared across
+/// `--concurrency` and `--batch-size` values.
+pub async fn run(args: &Args, bench: &BenchArgs) -> Result<()> {
+    let server = MockServer::start_with_load(SyntheticLoad {
+        latency: bench.latency,
+        mean_latency: Duration::from_millis(bench.latency_ms),
+        error_rate: bench.error_rate,
+        server_error_rate: bench.server_error_rate,
+    })
+    .await?;
+
+    let mut allow_cidr = args.allow_cidr.clone();
+    allow_cidr.push(server.ip());
+    let checker = crate::checker_builder(args)?
+        .network_policy(NetworkPolicy::new(
+            args.network_policy,
+            &allow_cidr,
+            &args.deny_cidr,
+        )?)
+        .record_exchanges(true)
+        .build()?;
+
+    println!(
+        "Bench: {} URLs against {} ({} latency, mean {}ms, {:.1}% 404, {:.1}% 503)",
+        bench.urls,
+        server.url("/"),
+        format!("{:?}", bench.latency).to_lowercase(),
+        bench.latency_ms,
+        bench.error_rate * 100.0,
+        bench.server_error_rate * 100.0
+    );
+    println!(
+        "Concurrency {}, batch size {}, {} retry attempts ({}s delay)",
+        args.concurrency, args.batch_size, args.retry_attempts, args.retry_delay
+    );
+    println!();
+
+    let usage_before = resource_usage();
+    let started = Instant::now();
+
+    let mut latencies = Vec::new();
+    let mut outcomes: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let ids: Vec<i64> = (0..bench.urls as i64).collect();
+    for chunk in ids.chunks(args.batch_size as usize) {
+        let batch = chunk
+            .iter()
+            .map(|id| (*id, server.url(&format!("/bench/{}.png", id))))
+            .collect();
+        for result in checker.check_batch(batch).await {
+            *outcomes.entry(result.outcome.as_str()).or_insert(0) += 1;
+            latencies.extend(result.exchanges.iter().map(|exchange| exchange.elapsed));
+        }
+    }
+
+    let elapsed = started.elapsed();
+    let usage_after = resource_usage();
+    latencies.sort();
+
+    println!(
+        "Checked {} URLs in {:.2}s: {:.1} URLs/s",
+        bench.urls,
+        elapsed.as_secs_f64(),
+        bench.urls as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "Requests: {} ({:.1}/s), latency p50 {}, p95 {}, p99 {}, max {}",
+        latencies.len(),
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        millis(percentile(&latencies, 50.0)),
+        millis(percentile(&latencies, 95.0)),
+        millis(percentile(&latencies, 99.0)),
+        millis(latencies.last().copied().unwrap_or_default())
+    );
+    let outcomes: Vec<String> = outcomes
+        .iter()
+        .map(|(outcome, count)| format!("{} {}", outcome, count))
+        .collect();
+    println!("Outcomes: {}", outcomes.join(", "));
+
+    match (usage_before, usage_after) {
+        (Some(before), Some(after)) => {
+            let user = after.user.saturating_sub(before.user);
+            let system = after.system.saturating_sub(before.system);
+            println!(
+                "Memory high-water mark: {:.1} MB",
+                after.max_rss_bytes as f64 / (1024.0 * 1024.0)
+            );
+            println!(
+                "CPU: {:.2}s user, {:.2}s system ({:.0}% of one core)",
+                user.as_secs_f64(),
+                system.as_secs_f64(),
+                (user + system).as_secs_f64() / elapsed.as_secs_f64() * 100.0
+            );
+        }
+        _ => println!("Memory and CPU usage: n/a on this platform"),
+    }
+
+    Ok(())
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Peak resident memory and CPU time of the process so far
+struct ResourceUsage {
+    max_rss_bytes: u64,
+    user: Duration,
+    system: Duration,
+}
+
+#[cfg(unix)]
+fn resource_usage() -> Option<ResourceUsage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes to the struct it is given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: the call succeeded, so the struct is initialized
+    let usage = unsafe { usage.assume_init() };
+
+    // Kilobytes on Linux, bytes on macOS
+    let max_rss = usage.ru_maxrss as u64;
+    let max_rss_bytes = if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    };
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    Some(ResourceUsage {
+        max_rss_bytes,
+        user: time(usage.ru_utime),
+        system: time(usage.ru_stime),
+    })
+}
+
+#[cfg(not(unix))]
+fn resource_usage() -> Option<ResourceUsage> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&values, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&values, 95.0), Duration::from_millis(95));
+        assert_eq!(percentile(&values, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&values, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&values[..1], 99.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resource_usage() {
+        let usage = resource_usage().unwrap();
+        assert!(usage.max_rss_bytes > 0);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::checker::{ErrorKind, FetchError};
+use crate::dns::{DnsCache, Lookup};
+use crate::trace::BODY_PREFIX_LEN;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use reqwest::{Method, RequestBuilder, Response, Url, Version};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Whether a cassette captures live traffic or stands in for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+/// Recorded DNS answers and HTTP responses, in the order they happened
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Interaction {
+    Dns {
+        domain: String,
+        lookup: Lookup,
+    },
+    Http {
+        method: String,
+        url: String,
+        #[serde(flatten)]
+        outcome: HttpOutcome,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum HttpOutcome {
+    Response(RecordedResponse),
+    Error { kind: ErrorKind, message: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    version: String,
+    headers: Vec<(String, String)>,
+    /// First bytes of the body, base64-encoded (HEAD responses have none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+/// Record/replay layer under the checker's DNS lookups and HTTP requests
+///
+/// A recording captures every DNS answer and HTTP response (headers and the first bytes
+/// of the body) a run depends on. Replaying it serves the same answers in the same order
+/// per domain and request, without network access, so the run's classifications repeat
+/// exactly.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: Mode,
+    path: PathBuf,
+    /// Interactions captured so far (record mode)
+    recorded: Mutex<Vec<Interaction>>,
+    /// Remaining DNS answers per domain (replay mode)
+    lookups: Mutex<HashMap<String, VecDeque<Lookup>>>,
+    /// Remaining responses per method and URL (replay mode)
+    responses: Mutex<HashMap<String, VecDeque<HttpOutcome>>>,
+}
+
+impl Cassette {
+    /// Start recording, to be written to `path` by `save`
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Self::new(Mode::Record, path.as_ref())
+    }
+
+    /// Load a recording to replay
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {:?}", path))?;
+        let file: CassetteFile = serde_json::from_str(&data)
+            .map_err(|e| anyhow!("Invalid cassette {:?}: {}", path, e))?;
+
+        let cassette = Self::new(Mode::Replay, path);
+        {
+            let mut lookups = cassette.lookups.lock().unwrap();
+            let mut responses = cassette.responses.lock().unwrap();
+            for interaction in file.interactions {
+                match interaction {
+                    Interaction::Dns { domain, lookup } => {
+                        lookups.entry(domain).or_default().push_back(lookup)
+                    }
+                    Interaction::Http {
+                        method,
+                        url,
+                        outcome,
+                    } => responses
+                        .entry(request_key(&method, &url))
+                        .or_default()
+                        .push_back(outcome),
+                }
+            }
+        }
+        Ok(cassette)
+    }
+
+    fn new(mode: Mode, path: &Path) -> Self {
+        Self {
+            mode,
+            path: path.to_path_buf(),
+            recorded: Mutex::new(Vec::new()),
+            lookups: Mutex::new(HashMap::new()),
+            responses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == Mode::Replay
+    }
+
+    /// Resolve a domain through the resolver, or from the recording
+    pub async fn lookup(&self, dns: &DnsCache, domain: &str) -> Lookup {
+        match self.mode {
+            Mode::Record => {
+                let lookup = dns.lookup(domain).await;
+                self.recorded.lock().unwrap().push(Interaction::Dns {
+                    domain: domain.to_string(),
+                    lookup: lookup.clone(),
+                });
+                lookup
+            }
+            Mode::Replay => self
+                .lookups
+                .lock()
+                .unwrap()
+                .get_mut(domain)
+                .and_then(|answers| answers.pop_front())
+                .unwrap_or_else(|| Lookup::Failed("not recorded in cassette".to_string())),
+        }
+    }
+
+    /// Send a request, or answer it from the recording
+    ///
+    /// Recorded responses are rebuilt from what was captured, so a live run and its
+    /// replay see identical responses.
+    pub async fn send(
+        &self,
+        request: RequestBuilder,
+        method: &Method,
+        url: &Url,
+        timeout: Duration,
+    ) -> Result<Response, FetchError> {
+        let outcome = match self.mode {
+            Mode::Record => {
+                let outcome = match request.send().await {
+                    Ok(response) => HttpOutcome::Response(capture(response, method, timeout).await),
+                    Err(e) => {
+                        let error = FetchError::from_reqwest(&e);
+                        HttpOutcome::Error {
+                            kind: error.kind,
+                            message: error.message,
+                        }
+                    }
+                };
+                self.recorded.lock().unwrap().push(Interaction::Http {
+                    method: method.to_string(),
+                    url: url.to_string(),
+                    outcome: outcome.clone(),
+                });
+                outcome
+            }
+            Mode::Replay => self
+                .responses
+                .lock()
+                .unwrap()
+                .get_mut(&request_key(method.as_str(), url.as_str()))
+                .and_then(|outcomes| outcomes.pop_front())
+                .ok_or_else(|| FetchError {
+                    kind: ErrorKind::Request,
+                    message: format!("No response recorded in cassette for {} {}", method, url),
+                })?,
+        };
+
+        match outcome {
+            HttpOutcome::Response(recorded) => rebuild(recorded),
+            HttpOutcome::Error { kind, message } => Err(FetchError { kind, message }),
+        }
+    }
+
+    /// Write the recording, or report recorded responses a replay never asked for
+    pub fn save(&self) -> Result<()> {
+        match self.mode {
+            Mode::Record => {
+                let file = CassetteFile {
+                    interactions: self.recorded.lock().unwrap().clone(),
+                };
+                std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+                    .with_context(|| format!("Failed to write cassette {:?}", self.path))?;
+                info!(
+                    "Recorded {} interactions to cassette {:?}",
+                    file.interactions.len(),
+                    self.path
+                );
+            }
+            Mode::Replay => {
+                let unused: usize = self
+                    .responses
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(VecDeque::len)
+                    .sum();
+                if unused > 0 {
+                    warn!(
+                        "{} recorded responses in cassette {:?} were not replayed; the run differs from the recorded one",
+                        unused, self.path
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn request_key(method: &str, url: &str) -> String {
+    format!("{} {}", method, url)
+}
+
+/// Capture a live response, reading the first bytes of its body
+async fn capture(mut response: Response, method: &Method, timeout: Duration) -> RecordedResponse {
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+
+    let mut body = None;
+    if method != Method::HEAD {
+        let mut bytes = Vec::new();
+        let read = async {
+            while bytes.len() < BODY_PREFIX_LEN {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => bytes.extend_from_slice(&chunk),
+                    _ => break,
+                }
+            }
+        };
+        let _ = tokio::time::timeout(timeout, read).await;
+        bytes.truncate(BODY_PREFIX_LEN);
+        body = Some(base64::engine::general_purpose::STANDARD.encode(&bytes));
+    }
+
+    RecordedResponse {
+        status: response.status().as_u16(),
+        version: format!("{:?}", response.version()),
+        headers,
+        body,
+    }
+}
+
+/// Build a response from a recording
+fn rebuild(recorded: RecordedResponse) -> Result<Response, FetchError> {
+    let invalid = |e: String| FetchError {
+        kind: ErrorKind::Request,
+        message: format!("Invalid response in cassette: {}", e),
+    };
+
+    let version = match recorded.version.as_str() {
+        "HTTP/0.9" => Version::HTTP_09,
+        "HTTP/1.0" => Version::HTTP_10,
+        "HTTP/2.0" => Version::HTTP_2,
+        "HTTP/3.0" => Version::HTTP_3,
+        _ => Version::HTTP_11,
+    };
+    let body = match &recorded.body {
+        Some(body) => base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .map_err(|e| invalid(e.to_string()))?,
+        None => Vec::new(),
+    };
+
+    let mut builder = http::Response::builder()
+        .status(recorded.status)
+        .version(version);
+    for (name, value) in &recorded.headers {
+        builder = builder.header(name, value);
+    }
+    let response = builder.body(body).map_err(|e| invalid(e.to_string()))?;
+    Ok(Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        std::fs::write(
+            &path,
+            r#"{"interactions": [
+                {"type": "dns", "domain": "img.example.com", "lookup": {"resolved": ["93.184.216.34"]}},
+                {"type": "http", "method": "HEAD", "url": "https://img.example.com/a.jpg",
+                 "response": {"status": 503, "version": "HTTP/1.1", "headers": [["retry-after", "5"]]}},
+                {"type": "http", "method": "HEAD", "url": "https://img.example.com/a.jpg",
+                 "error": {"kind": "timeout", "message": "operation timed out"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        let cassette = Cassette::replay(&path).unwrap();
+        assert!(cassette.is_replay());
+        assert_eq!(
+            cassette.lookup(&DnsCache::new(), "img.example.com").await,
+            Lookup::Resolved(vec!["93.184.216.34".parse().unwrap()])
+        );
+
+        let client = reqwest::Client::new();
+        let url = Url::parse("https://img.example.com/a.jpg").unwrap();
+        let send = || {
+            cassette.send(
+                client.head(url.clone()),
+                &Method::HEAD,
+                &url,
+                Duration::from_secs(1),
+            )
+        };
+
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["retry-after"], "5");
+
+        let error = send().await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Timeout);
+
+        let error = send().await.unwrap_err();
+        assert!(error.message.contains("No response recorded"));
+    }
+
+    #[test]
+    fn test_invalid_cassette() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        std::fs::write(&path, r#"{"interactions": [{"type": "ftp"}]}"#).unwrap();
+        assert!(Cassette::replay(&path).is_err());
+        assert!(Cassette::replay(dir.path().join("missing.json")).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::checker::{ErrorKind, FetchError};
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::Response;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Server errors injected, picked at random
+const SERVER_ERRORS: [u16; 4] = [500, 502, 503, 504];
+
+/// Share of requests (0.0-1.0) failed with each kind of injected fault
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultRates {
+    pub timeout: f64,
+    pub reset: f64,
+    pub server_error: f64,
+    pub rate_limit: f64,
+}
+
+impl FaultRates {
+    pub fn total(&self) -> f64 {
+        self.timeout + self.reset + self.server_error + self.rate_limit
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.total() > 0.0
+    }
+
+    /// Check that every rate is a probability and that together they don't exceed 1
+    pub fn validate(&self) -> Result<()> {
+        let rates = [self.timeout, self.reset, self.server_error, self.rate_limit];
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(anyhow!("Chaos rates must be between 0.0 and 1.0"));
+        }
+        if self.total() > 1.0 {
+            return Err(anyhow!("Chaos rates must add up to at most 1.0"));
+        }
+        Ok(())
+    }
+}
+
+/// A fault standing in for a real request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The request hangs until its timeout
+    Timeout,
+    /// The connection is reset before a response arrives
+    Reset,
+    /// The server answers with a 5xx status
+    ServerError(u16),
+    /// The server answers 429 with a Retry-After
+    RateLimit,
+}
+
+/// Injects random faults in place of requests, for testing retries and safeguards on staging
+///
+/// Each request draws at most one fault; a faulted request is never sent.
+#[derive(Debug)]
+pub struct FaultInjector {
+    rates: FaultRates,
+    rng: Mutex<StdRng>,
+    timeouts: AtomicU64,
+    resets: AtomicU64,
+    server_errors: AtomicU64,
+    rate_limits: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Create an injector; a seed makes the sequence of faults repeatable
+    pub fn new(rates: FaultRates, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        Self {
+            rates,
+            rng: Mutex::new(rng),
+            timeouts: AtomicU64::new(0),
+            resets: AtomicU64::new(0),
+            server_errors: AtomicU64::new(0),
+            rate_limits: AtomicU64::new(0),
+        }
+    }
+
+    /// Draw the fault for the next request, if any
+    pub fn pick(&self) -> Option<Fault> {
+        let mut rng = self.rng.lock().unwrap();
+        let roll: f64 = rng.random();
+
+        let mut threshold = self.rates.timeout;
+        if roll < threshold {
+            return Some(Fault::Timeout);
+        }
+        threshold += self.rates.reset;
+        if roll < threshold {
+            return Some(Fault::Reset);
+        }
+        threshold += self.rates.server_error;
+        if roll < threshold {
+            let status = SERVER_ERRORS[rng.random_range(0..SERVER_ERRORS.len())];
+            return Some(Fault::ServerError(status));
+        }
+        threshold += self.rates.rate_limit;
+        if roll < threshold {
+            return Some(Fault::RateLimit);
+        }
+        None
+    }
+
+    /// Play a fault as the outcome of a request
+    pub async fn inject(&self, fault: Fault, timeout: Duration) -> Result<Response, FetchError> {
+        match fault {
+            Fault::Timeout => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(timeout).await;
+                Err(FetchError {
+                    kind: ErrorKind::Timeout,
+                    message: "operation timed out (injected fault)".to_string(),
+                })
+            }
+            Fault::Reset => {
+                self.resets.fetch_add(1, Ordering::Relaxed);
+                Err(FetchError {
+                    kind: ErrorKind::Connect,
+                    message: "connection reset by peer (injected fault)".to_string(),
+                })
+            }
+            Fault::ServerError(status) => {
+                self.server_errors.fetch_add(1, Ordering::Relaxed);
+                Ok(response(status, None))
+            }
+            Fault::RateLimit => {
+                self.rate_limits.fetch_add(1, Ordering::Relaxed);
+                Ok(response(429, Some("1")))
+            }
+        }
+    }
+
+    /// Faults injected so far, as a log line
+    pub fn describe_counts(&self) -> String {
+        format!(
+            "{} timeouts, {} connection resets, {} server errors, {} rate limits",
+            self.timeouts.load(Ordering::Relaxed),
+            self.resets.load(Ordering::Relaxed),
+            self.server_errors.load(Ordering::Relaxed),
+            self.rate_limits.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Synthetic error response, marked as injected
+fn response(status: u16, retry_after: Option<&str>) -> Response {
+    let mut builder = http::Response::builder()
+        .status(status)
+        .header("content-type", "text/plain")
+        .header("x-injected-fault", "true");
+    if let Some(retry_after) = retry_after {
+        builder = builder.header("retry-after", retry_after);
+    }
+    let response = builder
+        .body("Injected fault")
+        .expect("static status and headers are valid");
+    Response::from(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Outcome;
+    use crate::mock_server::MockServer;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_validate_rates() {
+        assert!(FaultRates::default().validate().is_ok());
+        assert!(!FaultRates::default().is_enabled());
+
+        let rates = FaultRates {
+            timeout: 0.1,
+            server_error: 0.2,
+            ..Default::default()
+        };
+        assert!(rates.validate().is_ok());
+        assert!(rates.is_enabled());
+
+        let negative = FaultRates {
+            reset: -0.1,
+            ..Default::default()
+        };
+        assert!(negative.validate().is_err());
+
+        let too_many = FaultRates {
+            timeout: 0.6,
+            rate_limit: 0.6,
+            ..Default::default()
+        };
+        assert!(too_many.validate().is_err());
+    }
+
+    #[test]
+    fn test_pick_follows_rates() {
+        let never = FaultInjector::new(FaultRates::default(), Some(1));
+        assert!((0..100).all(|_| never.pick().is_none()));
+
+        let always = FaultInjector::new(
+            FaultRates {
+                rate_limit: 1.0,
+                ..Default::default()
+            },
+            Some(1),
+        );
+        assert!((0..100).all(|_| always.pick() == Some(Fault::RateLimit)));
+
+        // The same seed draws the same faults
+        let rates = FaultRates {
+            timeout: 0.25,
+            reset: 0.25,
+            server_error: 0.25,
+            rate_limit: 0.0,
+        };
+        let first = FaultInjector::new(rates, Some(42));
+        let second = FaultInjector::new(rates, Some(42));
+        let draws: Vec<_> = (0..50).map(|_| first.pick()).collect();
+        assert_eq!(draws, (0..50).map(|_| second.pick()).collect::<Vec<_>>());
+        assert!(draws.contains(&None));
+        assert!(draws
+            .iter()
+            .any(|fault| matches!(fault, Some(Fault::ServerError(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults_replace_requests() {
+        let server = MockServer::start().await.unwrap();
+        let injector = |rates| Some(Arc::new(FaultInjector::new(rates, Some(7))));
+
+        // Resets are not retried, and nothing reaches the server
+        let checker = server
+            .checker_builder()
+            .unwrap()
+            .fault_injection(injector(FaultRates {
+                reset: 1.0,
+                ..Default::default()
+            }))
+            .build()
+            .unwrap();
+        let results = checker
+            .check_batch(vec![(1, server.url("/image.png"))])
+            .await;
+        assert_eq!(results[0].outcome, Outcome::Broken);
+        assert_eq!(results[0].error.as_deref(), Some("Connection failed"));
+        assert_eq!(server.hits("/image.png"), 0);
+
+        // 429s are retried in every phase before the URL is given up
+        let checker = server
+            .checker_builder()
+            .unwrap()
+            .fault_injection(injector(FaultRates {
+                rate_limit: 1.0,
+                ..Default::default()
+            }))
+            .build()
+            .unwrap();
+        let results = checker
+            .check_batch(vec![(1, server.url("/image.png"))])
+            .await;
+        assert_eq!(results[0].status_code, Some(429));
+        assert!(results[0].is_retryable);
+        assert!(checker
+            .fault_injector()
+            .unwrap()
+            .describe_counts()
+            .contains("3 rate limits"));
+    }
+}
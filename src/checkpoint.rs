@@ -1,3 +1,5 @@
+use crate::key::RowKey;
+use crate::summary::RunSummary;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -7,6 +9,8 @@ use tracing::{debug, info, warn};
 
 const CHECKPOINT_DIR: &str = ".checkpoint";
 const CHECKPOINT_FILE: &str = "progress.json";
+/// Directory (inside the checkpoint directory) with one checkpoint per tenant schema
+const TENANTS_DIR: &str = "tenants";
 
 /// Checkpoint data for resume functionality
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,15 +24,21 @@ pub struct Checkpoint {
     /// Number of records processed so far
     pub processed: i64,
 
-    /// Last processed ID (for ID-based pagination)
-    pub last_id: i64,
+    /// Key of the last processed row (for keyset pagination)
+    #[serde(alias = "last_id")]
+    pub last_key: Option<RowKey>,
 
-    /// List of broken URL IDs found so far
-    pub broken_ids: Vec<i64>,
+    /// Keys of the broken URL rows found so far
+    #[serde(alias = "broken_ids")]
+    pub broken_keys: Vec<RowKey>,
 
     /// Total broken URLs found
     pub broken_count: i64,
 
+    /// Per-category and per-host counts of reported URLs
+    #[serde(default)]
+    pub summary: RunSummary,
+
     /// Timestamp when processing started
     pub started_at: DateTime<Utc>,
 
@@ -40,6 +50,10 @@ pub struct Checkpoint {
 
     /// Whether dry-run mode is enabled
     pub dry_run: bool,
+
+    /// Tenant schema of a multi-tenant run; each tenant has its own checkpoint file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
 impl Checkpoint {
@@ -50,51 +64,77 @@ impl Checkpoint {
             table: table.to_string(),
             total_records,
             processed: 0,
-            last_id: 0,
-            broken_ids: Vec::new(),
+            last_key: None,
+            broken_keys: Vec::new(),
             broken_count: 0,
+            summary: RunSummary::default(),
             started_at: now,
             updated_at: now,
             current_batch: 0,
             dry_run,
+            tenant: None,
         }
     }
 
-    /// Get checkpoint file path
-    fn get_checkpoint_path() -> PathBuf {
-        Path::new(CHECKPOINT_DIR).join(CHECKPOINT_FILE)
+    /// Checkpoint of one tenant schema
+    pub fn for_tenant(mut self, tenant: Option<&str>) -> Self {
+        self.tenant = tenant.map(str::to_string);
+        self
+    }
+
+    /// Get checkpoint file path: `progress.json`, or one file per tenant
+    fn get_checkpoint_path(tenant: Option<&str>) -> PathBuf {
+        let dir = Path::new(CHECKPOINT_DIR);
+        match tenant {
+            Some(tenant) => {
+                // Percent-encode everything but lowercase letters, digits, '_' and '-', so
+                // distinct schemas get distinct files, even on case-insensitive file systems
+                let name: String = tenant
+                    .bytes()
+                    .map(|byte| match byte {
+                        b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => (byte as char).to_string(),
+                        _ => format!("%{:02X}", byte),
+                    })
+                    .collect();
+                dir.join(TENANTS_DIR).join(format!("{}.json", name))
+            }
+            None => dir.join(CHECKPOINT_FILE),
+        }
     }
 
     /// Check if a checkpoint file exists
-    pub fn exists() -> bool {
-        Self::get_checkpoint_path().exists()
+    pub fn exists(tenant: Option<&str>) -> bool {
+        Self::get_checkpoint_path(tenant).exists()
     }
 
     /// Save checkpoint to file
     pub async fn save(&self) -> Result<()> {
-        let dir = Path::new(CHECKPOINT_DIR);
+        let path = Self::get_checkpoint_path(self.tenant.as_deref());
 
         // Create checkpoint directory if it doesn't exist
-        if !dir.exists() {
-            fs::create_dir_all(dir).await?;
+        if let Some(dir) = path.parent() {
+            if !dir.exists() {
+                fs::create_dir_all(dir).await?;
+            }
         }
 
-        let path = Self::get_checkpoint_path();
         let json = serde_json::to_string_pretty(self)?;
 
         fs::write(&path, &json).await?;
 
         debug!(
-            "Checkpoint saved: processed={}, last_id={}, broken={}",
-            self.processed, self.last_id, self.broken_count
+            "Checkpoint saved: processed={}, last_key={}, broken={}",
+            self.processed,
+            self.describe_last_key(),
+            self.broken_count
         );
 
         Ok(())
     }
 
     /// Load checkpoint from file
-    pub async fn load() -> Result<Option<Self>> {
-        let path = Self::get_checkpoint_path();
+    pub async fn load(tenant: Option<&str>) -> Result<Option<Self>> {
+        let path = Self::get_checkpoint_path(tenant);
 
         if !path.exists() {
             return Ok(None);
@@ -104,41 +144,51 @@ impl Checkpoint {
         let checkpoint: Self = serde_json::from_str(&data)?;
 
         info!(
-            "Loaded checkpoint: processed={}/{}, last_id={}, broken={}",
+            "Loaded checkpoint: processed={}/{}, last_key={}, broken={}",
             checkpoint.processed,
             checkpoint.total_records,
-            checkpoint.last_id,
+            checkpoint.describe_last_key(),
             checkpoint.broken_count
         );
 
         Ok(Some(checkpoint))
     }
 
+    /// Key of the last processed row, or `-` before the first batch
+    pub fn describe_last_key(&self) -> String {
+        self.last_key
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "-".to_string())
+    }
+
     /// Update checkpoint with new progress
-    pub fn update(&mut self, processed: i64, last_id: i64, new_broken_ids: Vec<i64>) {
+    pub fn update(&mut self, processed: i64, last_key: RowKey, new_broken_keys: Vec<RowKey>) {
         self.processed = processed;
-        self.last_id = last_id;
-        self.broken_count += new_broken_ids.len() as i64;
-        self.broken_ids.extend(new_broken_ids);
+        self.last_key = Some(last_key);
+        self.broken_count += new_broken_keys.len() as i64;
+        self.broken_keys.extend(new_broken_keys);
         self.updated_at = Utc::now();
         self.current_batch += 1;
     }
 
     /// Delete checkpoint file
-    pub async fn delete() -> Result<()> {
-        let path = Self::get_checkpoint_path();
+    pub async fn delete(tenant: Option<&str>) -> Result<()> {
+        let path = Self::get_checkpoint_path(tenant);
 
         if path.exists() {
             fs::remove_file(&path).await?;
             info!("Checkpoint deleted");
         }
 
-        // Also try to remove the directory if empty
+        // Also try to remove the directories if empty
         let dir = Path::new(CHECKPOINT_DIR);
-        if dir.exists() {
-            if let Ok(mut entries) = fs::read_dir(dir).await {
-                if entries.next_entry().await?.is_none() {
-                    let _ = fs::remove_dir(dir).await;
+        for dir in [dir.join(TENANTS_DIR), dir.to_path_buf()] {
+            if dir.exists() {
+                if let Ok(mut entries) = fs::read_dir(&dir).await {
+                    if matches!(entries.next_entry().await, Ok(None)) {
+                        let _ = fs::remove_dir(&dir).await;
+                    }
                 }
             }
         }
@@ -184,12 +234,70 @@ mod tests {
     #[test]
     fn test_checkpoint_update() {
         let mut cp = Checkpoint::new("test_table", 1000, false);
-        cp.update(100, 100, vec![1, 2, 3]);
+        cp.update(100, RowKey::from(100), vec![1.into(), 2.into(), 3.into()]);
 
         assert_eq!(cp.processed, 100);
-        assert_eq!(cp.last_id, 100);
+        assert_eq!(cp.last_key, Some(RowKey::from(100)));
         assert_eq!(cp.broken_count, 3);
-        assert_eq!(cp.broken_ids, vec![1, 2, 3]);
+        assert_eq!(cp.broken_keys, vec![1.into(), 2.into(), 3.into()]);
+    }
+
+    #[test]
+    fn test_load_integer_id_checkpoint() {
+        // Checkpoints written before composite and non-integer keys were supported
+        let json = r#"{
+            "table": "hotel_hotelproviderimage",
+            "total_records": 1000,
+            "processed": 200,
+            "last_id": 4711,
+            "broken_ids": [12, 99],
+            "broken_count": 2,
+            "started_at": "2025-01-01T00:00:00Z",
+            "updated_at": "2025-01-01T00:10:00Z",
+            "current_batch": 2,
+            "dry_run": true
+        }"#;
+        let cp: Checkpoint = serde_json::from_str(json).unwrap();
+        assert_eq!(cp.last_key, Some(RowKey::from(4711)));
+        assert_eq!(cp.broken_keys, vec![12.into(), 99.into()]);
+
+        // Composite keys round-trip
+        let mut cp = Checkpoint::new("images", 10, false);
+        cp.update(1, RowKey::parse("7,3"), vec![RowKey::parse("7,1")]);
+        let loaded: Checkpoint =
+            serde_json::from_str(&serde_json::to_string(&cp).unwrap()).unwrap();
+        assert_eq!(loaded.last_key, Some(RowKey::parse("7,3")));
+        assert_eq!(loaded.broken_keys, vec![RowKey::parse("7,1")]);
+    }
+
+    #[test]
+    fn test_checkpoint_path() {
+        assert_eq!(
+            Checkpoint::get_checkpoint_path(None),
+            Path::new(".checkpoint/progress.json")
+        );
+        assert_eq!(
+            Checkpoint::get_checkpoint_path(Some("tenant_a")),
+            Path::new(".checkpoint/tenants/tenant_a.json")
+        );
+        assert_eq!(
+            Checkpoint::get_checkpoint_path(Some("../x y")),
+            Path::new(".checkpoint/tenants/%2E%2E%2Fx%20y.json")
+        );
+
+        // Schemas differing only in punctuation or case don't share a checkpoint
+        let paths: std::collections::HashSet<_> = ["acme.eu", "acme_eu", "acme%2Eeu", "Acme_eu"]
+            .into_iter()
+            .map(|tenant| Checkpoint::get_checkpoint_path(Some(tenant)))
+            .collect();
+        assert_eq!(paths.len(), 4);
+
+        // Tenant checkpoints remember their tenant; single-table ones stay as they were
+        let cp = Checkpoint::new("tenant_a.images", 10, false).for_tenant(Some("tenant_a"));
+        let json = serde_json::to_string(&cp).unwrap();
+        assert!(json.contains(r#""tenant":"tenant_a""#));
+        let json = serde_json::to_string(&Checkpoint::new("images", 10, false)).unwrap();
+        assert!(!json.contains("tenant"));
     }
 
     #[test]
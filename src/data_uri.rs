@@ -0,0 +1,233 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::ValueEnum;
+use imagesize::ImageType;
+
+/// Maximum decoded payload size accepted for an inline image
+const MAX_PAYLOAD_BYTES: usize = 5 * 1024 * 1024;
+
+/// What to do with rows whose URL column holds a `data:` URI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DataUriAction {
+    /// Report data URIs but never delete them
+    Keep,
+    /// Delete data URIs whose payload is not a valid image
+    DeleteInvalid,
+    /// Delete every data URI, valid or not
+    Delete,
+}
+
+impl DataUriAction {
+    /// Whether a data URI row with the given validity should be deleted
+    pub fn should_delete(self, is_valid: bool) -> bool {
+        match self {
+            DataUriAction::Keep => false,
+            DataUriAction::DeleteInvalid => !is_valid,
+            DataUriAction::Delete => true,
+        }
+    }
+}
+
+/// A successfully decoded inline image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineImage {
+    pub mime_type: String,
+    pub size: usize,
+    pub dimensions: Option<(usize, usize)>,
+}
+
+/// Check if a value is a `data:` URI rather than a URL
+pub fn is_data_uri(value: &str) -> bool {
+    value
+        .get(..5)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("data:"))
+}
+
+/// Short description of a data URI for reports, without the payload
+pub fn summarize(value: &str) -> String {
+    let header = value.split_once(',').map(|(h, _)| h).unwrap_or(value);
+    format!("{} ({} bytes)", header, value.len())
+}
+
+/// Decode a data URI and validate its payload as an image
+///
+/// Checks the declared media type, the decoded size, that the payload is a
+/// decodable image and that its actual format matches the declared one.
+pub fn validate(value: &str) -> Result<InlineImage, String> {
+    let rest = value
+        .get(5..)
+        .ok_or_else(|| "Malformed data URI".to_string())?;
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "Malformed data URI: missing ','".to_string())?;
+
+    let mut params = header.split(';');
+    let mime_type = params
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let is_base64 = params.any(|p| p.trim().eq_ignore_ascii_case("base64"));
+
+    if !mime_type.starts_with("image/") {
+        let shown = if mime_type.is_empty() {
+            "text/plain"
+        } else {
+            &mime_type
+        };
+        return Err(format!("Data URI is not an image ({})", shown));
+    }
+
+    let payload = if is_base64 {
+        // Size known from the encoded length, so oversized payloads are never decoded
+        let encoded = data.bytes().filter(|b| !b.is_ascii_whitespace());
+        let padding = data
+            .trim_end()
+            .bytes()
+            .rev()
+            .take_while(|&b| b == b'=')
+            .count();
+        let decoded_len = (encoded.count() / 4 * 3).saturating_sub(padding);
+        if decoded_len > MAX_PAYLOAD_BYTES {
+            return Err(format!(
+                "Data URI payload too large ({} bytes, max {})",
+                decoded_len, MAX_PAYLOAD_BYTES
+            ));
+        }
+        let cleaned: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        STANDARD
+            .decode(&cleaned)
+            .map_err(|e| format!("Invalid base64 payload: {}", e))?
+    } else {
+        urlencoding::decode_binary(data.as_bytes()).into_owned()
+    };
+
+    if payload.is_empty() {
+        return Err("Empty data URI payload".to_string());
+    }
+
+    if payload.len() > MAX_PAYLOAD_BYTES {
+        return Err(format!(
+            "Data URI payload too large ({} bytes, max {})",
+            payload.len(),
+            MAX_PAYLOAD_BYTES
+        ));
+    }
+
+    // SVG is text, so only check that it actually contains an <svg> element
+    if mime_type == "image/svg+xml" {
+        let text = String::from_utf8_lossy(&payload).to_ascii_lowercase();
+        if !text.contains("<svg") {
+            return Err("SVG payload does not contain an <svg> element".to_string());
+        }
+        return Ok(InlineImage {
+            mime_type,
+            size: payload.len(),
+            dimensions: None,
+        });
+    }
+
+    let image_type = imagesize::image_type(&payload)
+        .map_err(|_| format!("Payload is not a recognized image ({})", mime_type))?;
+
+    if let Some(actual) = mime_for_type(&image_type) {
+        if normalize_mime(&mime_type) != actual {
+            return Err(format!("Declared {} but payload is {}", mime_type, actual));
+        }
+    }
+
+    let size =
+        imagesize::blob_size(&payload).map_err(|_| format!("Corrupted {} payload", mime_type))?;
+
+    if size.width == 0 || size.height == 0 {
+        return Err(format!(
+            "Image has zero dimensions ({}x{})",
+            size.width, size.height
+        ));
+    }
+
+    Ok(InlineImage {
+        mime_type,
+        size: payload.len(),
+        dimensions: Some((size.width, size.height)),
+    })
+}
+
+/// Canonical media type for a sniffed image format, if it is a common one
+fn mime_for_type(image_type: &ImageType) -> Option<&'static str> {
+    match image_type {
+        ImageType::Png => Some("image/png"),
+        ImageType::Jpeg => Some("image/jpeg"),
+        ImageType::Gif => Some("image/gif"),
+        ImageType::Webp => Some("image/webp"),
+        ImageType::Bmp => Some("image/bmp"),
+        ImageType::Ico => Some("image/x-icon"),
+        ImageType::Tiff => Some("image/tiff"),
+        _ => None,
+    }
+}
+
+/// Map media type aliases to the canonical names used by `mime_for_type`
+fn normalize_mime(mime_type: &str) -> &str {
+    match mime_type {
+        "image/jpg" | "image/pjpeg" => "image/jpeg",
+        "image/ico" | "image/vnd.microsoft.icon" => "image/x-icon",
+        "image/x-ms-bmp" => "image/bmp",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1x1 transparent PNG
+    const PNG_1X1: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn test_is_data_uri() {
+        assert!(is_data_uri("data:image/png;base64,AAAA"));
+        assert!(is_data_uri("DATA:image/png;base64,AAAA"));
+        assert!(!is_data_uri("https://example.com/a.png"));
+        assert!(!is_data_uri("dat"));
+    }
+
+    #[test]
+    fn test_validate_png() {
+        let uri = format!("data:image/png;base64,{}", PNG_1X1);
+        let image = validate(&uri).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!(image.dimensions, Some((1, 1)));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_payloads() {
+        assert!(validate("data:text/plain;base64,aGVsbG8=").is_err());
+        assert!(validate("data:image/png;base64,").is_err());
+        assert!(validate("data:image/png;base64,!!!notbase64").is_err());
+        assert!(validate("data:image/png;base64,aGVsbG8=").is_err());
+        // Declared type does not match the actual payload
+        let uri = format!("data:image/jpeg;base64,{}", PNG_1X1);
+        assert!(validate(&uri).is_err());
+
+        // Oversized payloads are rejected from their encoded length, before decoding
+        let uri = format!("data:image/png;base64,{}", "!".repeat(8 * 1024 * 1024));
+        assert!(validate(&uri)
+            .unwrap_err()
+            .starts_with("Data URI payload too large"));
+    }
+
+    #[test]
+    fn test_validate_svg() {
+        assert!(validate("data:image/svg+xml,%3Csvg%20xmlns%3D%22x%22%2F%3E").is_ok());
+        assert!(validate("data:image/svg+xml,hello").is_err());
+    }
+
+    #[test]
+    fn test_data_uri_action() {
+        assert!(!DataUriAction::Keep.should_delete(false));
+        assert!(DataUriAction::DeleteInvalid.should_delete(false));
+        assert!(!DataUriAction::DeleteInvalid.should_delete(true));
+        assert!(DataUriAction::Delete.should_delete(true));
+    }
+}
@@ -1,193 +1,610 @@
-use anyhow::Result;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{PgPool, Row};
+mod mysql;
+mod postgres;
+mod sqlite;
+
+pub use mysql::MySqlBackend;
+pub use postgres::PostgresBackend;
+pub use sqlite::SqliteBackend;
+
+use crate::key::{KeyValue, RowKey};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::fmt;
 use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Rows per backup or delete statement, to stay below query size limits
+const CHUNK_SIZE: usize = 1000;
+
+/// Database engine, chosen from the scheme of the database URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    MySql,
+    Sqlite,
+}
+
+impl Dialect {
+    /// Dialect of a `postgres://`, `mysql://` or `sqlite:` URL
+    pub fn from_url(db_url: &str) -> Result<Self> {
+        let scheme = db_url
+            .split_once(':')
+            .map(|(scheme, _)| scheme.to_ascii_lowercase())
+            .unwrap_or_default();
+        match scheme.as_str() {
+            "postgres" | "postgresql" => Ok(Dialect::Postgres),
+            "mysql" | "mariadb" => Ok(Dialect::MySql),
+            "sqlite" => Ok(Dialect::Sqlite),
+            _ => Err(anyhow!(
+                "Unsupported database URL scheme {:?} (expected postgres://, mysql:// or sqlite:)",
+                scheme
+            )),
+        }
+    }
+
+    /// Quote an identifier so that it is used verbatim (case and special characters)
+    pub fn quote(self, identifier: &str) -> String {
+        match self {
+            Dialect::MySql => format!("`{}`", identifier.replace('`', "``")),
+            Dialect::Postgres | Dialect::Sqlite => {
+                format!("\"{}\"", identifier.replace('"', "\"\""))
+            }
+        }
+    }
+
+    /// Quote a name, qualified by its schema if it has one: `"schema"."table"`
+    pub fn quote_qualified(self, schema: Option<&str>, name: &str) -> String {
+        match schema {
+            Some(schema) => format!("{}.{}", self.quote(schema), self.quote(name)),
+            None => self.quote(name),
+        }
+    }
+
+    /// Bind parameter `index` (1-based)
+    fn param(self, index: usize) -> String {
+        match self {
+            Dialect::Postgres => format!("${}", index),
+            Dialect::MySql | Dialect::Sqlite => "?".to_string(),
+        }
+    }
+
+    /// Parameters `first..first + count` as a row value: `$1` or `($1, $2)`
+    fn row_params(self, first: usize, count: usize) -> String {
+        let params: Vec<String> = (first..first + count).map(|i| self.param(i)).collect();
+        match params.as_slice() {
+            [param] => param.clone(),
+            _ => format!("({})", params.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dialect::Postgres => "PostgreSQL",
+            Dialect::MySql => "MySQL",
+            Dialect::Sqlite => "SQLite",
+        })
+    }
+}
+
+/// Column of a table as described by the database catalog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// Declared type, e.g. `bigint`, `character varying(255)` or `int(11) unsigned`
+    pub data_type: String,
+}
+
+/// What a column holds, judged from its declared type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Integer,
+    Uuid,
+    Text,
+    Other,
+}
+
+impl ColumnKind {
+    pub fn of(dialect: Dialect, data_type: &str) -> Self {
+        let data_type = data_type.trim().to_ascii_lowercase();
+        let base = data_type
+            .split(|c: char| c == '(' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+
+        match base {
+            "smallint" | "integer" | "bigint" | "int" | "int2" | "int4" | "int8" | "tinyint"
+            | "mediumint" | "smallserial" | "serial" | "bigserial" => ColumnKind::Integer,
+            "uuid" => ColumnKind::Uuid,
+            "text" | "varchar" | "char" | "character" | "citext" | "tinytext" | "mediumtext"
+            | "longtext" | "nvarchar" | "nchar" | "clob" => ColumnKind::Text,
+            // SQLite accepts any type name and decides by affinity (or none at all)
+            _ if dialect == Dialect::Sqlite => {
+                if data_type.contains("int") {
+                    ColumnKind::Integer
+                } else if data_type.is_empty()
+                    || ["char", "clob", "text"]
+                        .iter()
+                        .any(|t| data_type.contains(t))
+                {
+                    ColumnKind::Text
+                } else {
+                    ColumnKind::Other
+                }
+            }
+            _ => ColumnKind::Other,
+        }
+    }
+}
+
+/// A table as listed in the catalog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableName {
+    pub schema: String,
+    pub name: String,
+}
+
+impl fmt::Display for TableName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.schema, self.name)
+    }
+}
+
+/// The image table and the columns the checker reads
+#[derive(Debug, Clone)]
+pub struct Table {
+    /// Schema (PostgreSQL), database (MySQL) or attached database (SQLite) of the table;
+    /// the connection's default when not given, and always set once checked
+    pub schema: Option<String>,
+    pub name: String,
+    /// Primary key columns (several for a composite key)
+    pub key_columns: Vec<String>,
+    pub url_column: String,
+    /// Kind of each key column, once checked against the catalog
+    pub key_kinds: Vec<ColumnKind>,
+    /// Schema to create the backup table in (the table's own by default)
+    pub backup_schema: Option<String>,
+}
+
+impl Table {
+    pub fn new(name: String, key_columns: Vec<String>, url_column: String) -> Self {
+        Self {
+            schema: None,
+            name,
+            key_columns,
+            url_column,
+            key_kinds: Vec::new(),
+            backup_schema: None,
+        }
+    }
+
+    pub fn with_schema(mut self, schema: Option<String>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn with_backup_schema(mut self, backup_schema: Option<String>) -> Self {
+        self.backup_schema = backup_schema;
+        self
+    }
+
+    /// `schema.table`, or just the table name without a schema
+    pub fn qualified_name(&self) -> String {
+        qualify(self.schema.as_deref(), &self.name)
+    }
+
+    /// Table receiving deleted rows, prefixed with the table's schema when it goes to
+    /// another one (so that the tables of several tenant schemas can share it)
+    pub fn backup_name(&self) -> String {
+        match (&self.schema, self.backup_elsewhere()) {
+            (Some(schema), true) => format!("{}_{}_deleted_backup", schema, self.name),
+            _ => format!("{}_deleted_backup", self.name),
+        }
+    }
+
+    /// Schema of the backup table
+    pub fn backup_schema(&self) -> Option<&str> {
+        self.backup_schema.as_deref().or(self.schema.as_deref())
+    }
+
+    /// `schema.table` of the backup table
+    pub fn qualified_backup_name(&self) -> String {
+        qualify(self.backup_schema(), &self.backup_name())
+    }
+
+    /// Schema of a table checked against the catalog
+    fn checked_schema(&self) -> &str {
+        self.schema.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the backup table goes to a schema other than the table's
+    fn backup_elsewhere(&self) -> bool {
+        self.backup_schema.is_some() && self.backup_schema != self.schema
+    }
+
+    /// Quoted (and schema-qualified) table name
+    fn sql_name(&self, dialect: Dialect) -> String {
+        dialect.quote_qualified(self.schema.as_deref(), &self.name)
+    }
+
+    /// Quoted (and schema-qualified) backup table name
+    fn sql_backup_name(&self, dialect: Dialect) -> String {
+        dialect.quote_qualified(self.backup_schema(), &self.backup_name())
+    }
+
+    /// Quoted URL column
+    fn sql_url_column(&self, dialect: Dialect) -> String {
+        dialect.quote(&self.url_column)
+    }
+
+    /// Key columns for select lists and ORDER BY: `"a", "b"`
+    fn key_list(&self, dialect: Dialect) -> String {
+        let columns: Vec<String> = self.key_columns.iter().map(|c| dialect.quote(c)).collect();
+        columns.join(", ")
+    }
+
+    /// Key columns as a row value: `"a"` or `("a", "b")`
+    fn key_row(&self, dialect: Dialect) -> String {
+        match self.key_columns.as_slice() {
+            [column] => dialect.quote(column),
+            _ => format!("({})", self.key_list(dialect)),
+        }
+    }
+
+    /// Condition selecting the rows after a key, binding it from parameter `first_param`
+    fn keys_after(&self, dialect: Dialect, first_param: usize) -> String {
+        format!(
+            "{} > {}",
+            self.key_row(dialect),
+            dialect.row_params(first_param, self.key_columns.len())
+        )
+    }
+
+    /// Condition selecting `count` keys, bound in order from parameter 1
+    fn keys_in(&self, dialect: Dialect, count: usize) -> String {
+        let width = self.key_columns.len();
+        let rows: Vec<String> = (0..count)
+            .map(|i| dialect.row_params(1 + i * width, width))
+            .collect();
+        format!("{} IN ({})", self.key_row(dialect), rows.join(", "))
+    }
+}
+
+fn qualify(schema: Option<&str>, name: &str) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", schema, name),
+        None => name.to_string(),
+    }
+}
+
+/// Queries of one database engine against the image table
+///
+/// Implemented for PostgreSQL, MySQL and SQLite; [`Database`] adds logging and chunking
+/// on top. Backends check their table against the catalog when connecting (see
+/// [`resolve_table`]) and quote every identifier they put in a query.
+pub trait Backend: Send + Sync {
+    /// The table as found in the catalog
+    fn table(&self) -> &Table;
+
+    /// Base tables matching `name` case-insensitively, in schemas matching `schema` the
+    /// same way (the tables the connection sees without a schema); views are left out,
+    /// as rows can't be deleted through them
+    fn find_tables<'a>(
+        &'a self,
+        schema: Option<&'a str>,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<TableName>>>;
+
+    /// Columns of a table in definition order (none if the table doesn't exist)
+    fn columns<'a>(
+        &'a self,
+        schema: &'a str,
+        table: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ColumnInfo>>>;
+
+    /// Count records with non-null, non-empty URLs
+    fn count(&self) -> BoxFuture<'_, Result<i64>>;
+
+    /// Fetch the URL of a single row
+    fn fetch_url<'a>(&'a self, key: &'a RowKey) -> BoxFuture<'a, Result<Option<String>>>;
+
+    /// Fetch up to `limit` rows with a URL and a key above `after` (all rows without
+    /// one), in key order
+    fn fetch_batch<'a>(
+        &'a self,
+        after: Option<&'a RowKey>,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<(RowKey, String)>>>;
+
+    /// Create the backup table if it doesn't exist: the table's columns plus `deleted_at`
+    fn ensure_backup_table(&self) -> BoxFuture<'_, Result<()>>;
+
+    /// Copy rows to the backup table, skipping rows already backed up
+    fn backup<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>>;
+
+    /// Delete rows by key
+    fn delete<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>>;
+
+    /// Close the connection pool
+    fn close(&self) -> BoxFuture<'_, ()>;
+}
+
+/// Quoted column list of a table for copying rows to its backup (without `deleted_at`)
+fn backup_columns(dialect: Dialect, columns: &[ColumnInfo]) -> String {
+    let columns: Vec<String> = columns
+        .iter()
+        .filter(|column| column.name != "deleted_at")
+        .map(|column| dialect.quote(&column.name))
+        .collect();
+    columns.join(", ")
+}
+
+/// Position of a name exactly as given, or else of its only case-insensitive match
+fn find_name(wanted: &str, names: &[String]) -> Option<usize> {
+    names.iter().position(|name| name == wanted).or_else(|| {
+        let mut matches = names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| name.eq_ignore_ascii_case(wanted));
+        match (matches.next(), matches.next()) {
+            (Some((index, _)), None) => Some(index),
+            _ => None,
+        }
+    })
+}
+
+/// Check a table and its columns against the catalog, returning them named as there
+///
+/// Names match exactly, or case-insensitively when that is unambiguous (as unquoted
+/// names did before). The URL column must hold text and the key columns integers, UUIDs
+/// or text.
+pub async fn resolve_table(
+    backend: &dyn Backend,
+    dialect: Dialect,
+    table: &Table,
+) -> Result<Table> {
+    let wanted = table.qualified_name();
+    let tables = backend
+        .find_tables(table.schema.as_deref(), &table.name)
+        .await?;
+    // Compared as given: with the schema if there is one
+    let names: Vec<String> = tables
+        .iter()
+        .map(|found| qualify(table.schema.as_ref().map(|_| &*found.schema), &found.name))
+        .collect();
+    let Some(found) = find_name(&wanted, &names).map(|index| &tables[index]) else {
+        return Err(if tables.is_empty() {
+            anyhow!("Table {:?} does not exist", wanted)
+        } else {
+            anyhow!(
+                "Table {:?} does not exist; tables differing only in case: {}",
+                wanted,
+                names.join(", ")
+            )
+        });
+    };
+    let name = found.to_string();
+    if found.name != table.name || table.schema.as_ref().is_some_and(|s| *s != found.schema) {
+        info!("Using table {:?} for {:?}", name, wanted);
+    }
+
+    let columns = backend.columns(&found.schema, &found.name).await?;
+    let names: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+    let column = |wanted: &str| {
+        find_name(wanted, &names)
+            .map(|index| &columns[index])
+            .ok_or_else(|| {
+                anyhow!(
+                    "Column {:?} does not exist in table {:?} (columns: {})",
+                    wanted,
+                    name,
+                    names.join(", ")
+                )
+            })
+    };
+
+    let url_column = column(&table.url_column)?;
+    if ColumnKind::of(dialect, &url_column.data_type) != ColumnKind::Text {
+        return Err(anyhow!(
+            "URL column {:?} of table {:?} has type {}, expected a text type",
+            url_column.name,
+            name,
+            url_column.data_type
+        ));
+    }
+
+    let mut key_columns = Vec::new();
+    let mut key_kinds = Vec::new();
+    for wanted in &table.key_columns {
+        let key_column = column(wanted)?;
+        let kind = ColumnKind::of(dialect, &key_column.data_type);
+        if kind == ColumnKind::Other {
+            return Err(anyhow!(
+                "Key column {:?} of table {:?} has type {}; integer, UUID and text keys are supported",
+                key_column.name,
+                name,
+                key_column.data_type
+            ));
+        }
+        key_columns.push(key_column.name.clone());
+        key_kinds.push(kind);
+    }
+
+    Ok(Table {
+        schema: Some(found.schema.clone()),
+        name: found.name.clone(),
+        key_columns,
+        url_column: url_column.name.clone(),
+        key_kinds,
+        backup_schema: table.backup_schema.clone(),
+    })
+}
 
 /// Database connection and operations handler
 pub struct Database {
-    pool: PgPool,
-    table: String,
-    id_column: String,
-    url_column: String,
+    backend: Box<dyn Backend>,
 }
 
 impl Database {
-    /// Create a new database connection pool
-    pub async fn new(
-        db_url: &str,
-        table: String,
-        id_column: String,
-        url_column: String,
-    ) -> Result<Self> {
-        info!("Connecting to database...");
-
-        let pool = PgPoolOptions::new()
-            .max_connections(10)
-            .connect(db_url)
-            .await?;
+    /// Connect to the database of a `postgres://`, `mysql://` or `sqlite:` URL
+    ///
+    /// Fails if the table or its columns don't exist or have unexpected types.
+    pub async fn new(db_url: &str, table: Table) -> Result<Self> {
+        let dialect = Dialect::from_url(db_url)?;
+        info!("Connecting to {} database...", dialect);
+
+        let backend: Box<dyn Backend> = match dialect {
+            Dialect::Postgres => Box::new(PostgresBackend::connect(db_url, table).await?),
+            Dialect::MySql => Box::new(MySqlBackend::connect(db_url, table).await?),
+            Dialect::Sqlite => Box::new(SqliteBackend::connect(db_url, table).await?),
+        };
 
         info!("Database connection established");
 
-        Ok(Self {
-            pool,
-            table,
-            id_column,
-            url_column,
-        })
+        Ok(Self::with_backend(backend))
     }
 
-    /// Get total count of records with non-null URLs
-    pub async fn get_total_count(&self) -> Result<i64> {
-        let query = format!(
-            "SELECT COUNT(*) as count FROM {} WHERE {} IS NOT NULL AND {} != ''",
-            self.table, self.url_column, self.url_column
-        );
+    /// Use a backend that is already connected
+    pub fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Self { backend }
+    }
 
-        let row = sqlx::query(&query).fetch_one(&self.pool).await?;
+    pub fn table(&self) -> &Table {
+        self.backend.table()
+    }
 
-        let count: i64 = row.get("count");
+    /// Get total count of records with non-null URLs
+    pub async fn get_total_count(&self) -> Result<i64> {
+        let count = self.backend.count().await?;
         debug!("Total records with URLs: {}", count);
 
         Ok(count)
     }
 
-    /// Fetch a batch of URLs starting from a given ID
-    /// Uses ID-based pagination which is more efficient than OFFSET for large tables
-    pub async fn fetch_batch(&self, start_id: i64, limit: i64) -> Result<Vec<(i64, String)>> {
-        let query = format!(
-            "SELECT {}, {} FROM {} WHERE {} > $1 AND {} IS NOT NULL AND {} != '' ORDER BY {} LIMIT $2",
-            self.id_column,
-            self.url_column,
-            self.table,
-            self.id_column,
-            self.url_column,
-            self.url_column,
-            self.id_column
-        );
-
-        let rows = sqlx::query(&query)
-            .bind(start_id)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await?;
+    /// Fetch the URL of a single row
+    pub async fn fetch_url(&self, key: &RowKey) -> Result<Option<String>> {
+        self.check_key(key)?;
+        self.backend.fetch_url(key).await
+    }
 
-        let results: Vec<(i64, String)> = rows
-            .into_iter()
-            .map(|row| {
-                let id: i64 = row.get(0);
-                let url: String = row.get(1);
-                (id, url)
-            })
-            .collect();
+    /// Fetch a batch of URLs after a given key (from the start without one)
+    /// Uses keyset pagination which is more efficient than OFFSET for large tables
+    pub async fn fetch_batch(
+        &self,
+        after: Option<&RowKey>,
+        limit: i64,
+    ) -> Result<Vec<(RowKey, String)>> {
+        if let Some(key) = after {
+            self.check_key(key)?;
+        }
+        let results = self.backend.fetch_batch(after, limit).await?;
 
         debug!(
-            "Fetched {} records starting from ID {}",
+            "Fetched {} records after key {}",
             results.len(),
-            start_id
+            after
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "-".to_string())
         );
 
         Ok(results)
     }
 
-    /// Get the backup table name
-    fn get_backup_table_name(&self) -> String {
-        format!("{}_deleted_backup", self.table)
+    /// Key given as comma-separated values (e.g. `7,3`), typed by the key columns
+    pub fn parse_key(&self, key: &str) -> Result<RowKey> {
+        let table = self.table();
+        if table.key_kinds.is_empty() {
+            return Ok(RowKey::parse(key));
+        }
+
+        let values: Vec<&str> = key.split(',').map(str::trim).collect();
+        if values.len() != table.key_kinds.len() {
+            return Err(anyhow!(
+                "Key {:?} has {} value(s), but the key of {} is ({})",
+                key,
+                values.len(),
+                table.qualified_name(),
+                table.key_columns.join(", ")
+            ));
+        }
+        let values = values
+            .into_iter()
+            .zip(&table.key_kinds)
+            .zip(&table.key_columns)
+            .map(|((value, kind), column)| match kind {
+                ColumnKind::Integer => value
+                    .parse()
+                    .map(KeyValue::Int)
+                    .map_err(|_| anyhow!("Key column {} holds integers, got {:?}", column, value)),
+                ColumnKind::Uuid => Uuid::parse_str(value)
+                    .map(KeyValue::Uuid)
+                    .map_err(|_| anyhow!("Key column {} holds UUIDs, got {:?}", column, value)),
+                ColumnKind::Text | ColumnKind::Other => Ok(KeyValue::Text(value.to_string())),
+            })
+            .collect::<Result<_>>()?;
+        Ok(RowKey::new(values))
+    }
+
+    /// Make sure a key (e.g. from a checkpoint or the command line) fits the key columns
+    fn check_key(&self, key: &RowKey) -> Result<()> {
+        let table = self.table();
+        if key.len() != table.key_columns.len() {
+            return Err(anyhow!(
+                "Key {} has {} value(s), but the key of {} is ({})",
+                key,
+                key.len(),
+                table.qualified_name(),
+                table.key_columns.join(", ")
+            ));
+        }
+        Ok(())
     }
 
     /// Create backup table if it doesn't exist (copies structure from original table)
     pub async fn ensure_backup_table(&self) -> Result<()> {
-        let backup_table = self.get_backup_table_name();
-
-        // Create backup table with same structure + deleted_at timestamp
-        let query = format!(
-            r#"
-            CREATE TABLE IF NOT EXISTS {} (
-                LIKE {} INCLUDING ALL,
-                deleted_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
-            )
-            "#,
-            backup_table, self.table
-        );
-
-        sqlx::query(&query).execute(&self.pool).await?;
+        self.backend.ensure_backup_table().await?;
 
-        info!("Backup table '{}' ready", backup_table);
+        info!(
+            "Backup table '{}' ready",
+            self.table().qualified_backup_name()
+        );
         Ok(())
     }
 
     /// Backup records to backup table before deletion
-    async fn backup_records(&self, ids: &[i64]) -> Result<u64> {
-        if ids.is_empty() {
-            return Ok(0);
-        }
-
-        let backup_table = self.get_backup_table_name();
+    async fn backup_records(&self, keys: &[RowKey]) -> Result<u64> {
         let mut total_backed_up: u64 = 0;
-
-        // Process in chunks of 1000
-        for chunk in ids.chunks(1000) {
-            // Get column names from original table (excluding deleted_at which is auto-added)
-            let columns_query = format!(
-                r#"
-                SELECT string_agg(column_name, ', ') as cols
-                FROM information_schema.columns
-                WHERE table_name = $1
-                AND column_name != 'deleted_at'
-                "#
-            );
-
-            let row = sqlx::query(&columns_query)
-                .bind(&self.table)
-                .fetch_one(&self.pool)
-                .await?;
-
-            let columns: String = row.get("cols");
-
-            // Insert into backup table
-            let insert_query = format!(
-                "INSERT INTO {} ({}) SELECT {} FROM {} WHERE {} = ANY($1) ON CONFLICT DO NOTHING",
-                backup_table, columns, columns, self.table, self.id_column
-            );
-
-            let result = sqlx::query(&insert_query)
-                .bind(chunk)
-                .execute(&self.pool)
-                .await?;
-
-            total_backed_up += result.rows_affected();
+        for chunk in keys.chunks(CHUNK_SIZE) {
+            total_backed_up += self.backend.backup(chunk).await?;
         }
 
-        info!("Backed up {} records to '{}'", total_backed_up, backup_table);
+        info!(
+            "Backed up {} records to '{}'",
+            total_backed_up,
+            self.table().qualified_backup_name()
+        );
         Ok(total_backed_up)
     }
 
-    /// Delete records by their IDs in batches (with optional backup)
-    pub async fn delete_by_ids(&self, ids: &[i64], backup: bool) -> Result<u64> {
-        if ids.is_empty() {
+    /// Delete records by their keys in batches (with optional backup)
+    pub async fn delete_by_keys(&self, keys: &[RowKey], backup: bool) -> Result<u64> {
+        if keys.is_empty() {
             return Ok(0);
         }
+        for key in keys {
+            self.check_key(key)?;
+        }
 
         // Backup records first if requested
         if backup {
             self.ensure_backup_table().await?;
-            self.backup_records(ids).await?;
+            self.backup_records(keys).await?;
         }
 
         let mut total_deleted: u64 = 0;
-
-        // Process in chunks of 1000 to avoid query size limits
-        for chunk in ids.chunks(1000) {
-            let query = format!(
-                "DELETE FROM {} WHERE {} = ANY($1)",
-                self.table, self.id_column
-            );
-
-            let result = sqlx::query(&query)
-                .bind(chunk)
-                .execute(&self.pool)
-                .await?;
-
-            total_deleted += result.rows_affected();
+        for chunk in keys.chunks(CHUNK_SIZE) {
+            total_deleted += self.backend.delete(chunk).await?;
         }
 
         info!("Deleted {} records from database", total_deleted);
@@ -196,14 +613,361 @@ impl Database {
     }
 
     /// Close the database connection pool
-    pub async fn close(self) {
-        self.pool.close().await;
+    pub async fn close(&self) {
+        self.backend.close().await;
         info!("Database connection closed");
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // Integration tests would require a running PostgreSQL instance
-    // Unit tests can mock the database interactions
+    // PostgreSQL and MySQL need a running server; SQLite runs against a temporary file
+    use super::*;
+    use sqlx::Row;
+
+    #[test]
+    fn test_dialect_from_url() {
+        let dialect = |url| Dialect::from_url(url).unwrap();
+        assert_eq!(dialect("postgres://u:p@localhost/db"), Dialect::Postgres);
+        assert_eq!(dialect("postgresql://localhost/db"), Dialect::Postgres);
+        assert_eq!(dialect("mysql://u:p@localhost:3306/db"), Dialect::MySql);
+        assert_eq!(dialect("mariadb://localhost/db"), Dialect::MySql);
+        assert_eq!(dialect("sqlite://dev.db"), Dialect::Sqlite);
+        assert_eq!(dialect("sqlite::memory:"), Dialect::Sqlite);
+        assert!(Dialect::from_url("mssql://localhost/db").is_err());
+        assert!(Dialect::from_url("localhost/db").is_err());
+    }
+
+    #[test]
+    fn test_key_conditions() {
+        let table = Table::new(
+            "images".to_string(),
+            vec!["hotel_id".to_string(), "position".to_string()],
+            "url".to_string(),
+        );
+        assert_eq!(
+            table.keys_after(Dialect::Postgres, 1),
+            r#"("hotel_id", "position") > ($1, $2)"#
+        );
+        assert_eq!(
+            table.keys_in(Dialect::Postgres, 2),
+            r#"("hotel_id", "position") IN (($1, $2), ($3, $4))"#
+        );
+        assert_eq!(
+            table.keys_in(Dialect::MySql, 2),
+            "(`hotel_id`, `position`) IN ((?, ?), (?, ?))"
+        );
+
+        let table = Table {
+            key_columns: vec!["Id".to_string()],
+            ..table
+        };
+        assert_eq!(table.keys_after(Dialect::Sqlite, 1), r#""Id" > ?"#);
+        assert_eq!(
+            table.keys_in(Dialect::Postgres, 3),
+            r#""Id" IN ($1, $2, $3)"#
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(Dialect::Postgres.quote("HotelImage"), r#""HotelImage""#);
+        assert_eq!(Dialect::Sqlite.quote(r#"a"b"#), r#""a""b""#);
+        assert_eq!(Dialect::MySql.quote("a`b"), "`a``b`");
+    }
+
+    #[test]
+    fn test_column_kind() {
+        let kind = ColumnKind::of;
+        assert_eq!(kind(Dialect::Postgres, "bigint"), ColumnKind::Integer);
+        assert_eq!(kind(Dialect::Postgres, "uuid"), ColumnKind::Uuid);
+        assert_eq!(
+            kind(Dialect::Postgres, "character varying(255)"),
+            ColumnKind::Text
+        );
+        assert_eq!(kind(Dialect::Postgres, "jsonb"), ColumnKind::Other);
+        assert_eq!(
+            kind(Dialect::MySql, "int(11) unsigned"),
+            ColumnKind::Integer
+        );
+        assert_eq!(kind(Dialect::MySql, "varchar(512)"), ColumnKind::Text);
+        assert_eq!(kind(Dialect::MySql, "blob"), ColumnKind::Other);
+        assert_eq!(
+            kind(Dialect::Sqlite, "UNSIGNED BIG INT"),
+            ColumnKind::Integer
+        );
+        assert_eq!(kind(Dialect::Sqlite, "NVARCHAR(100)"), ColumnKind::Text);
+        assert_eq!(kind(Dialect::Sqlite, ""), ColumnKind::Text);
+        assert_eq!(kind(Dialect::Sqlite, "BLOB"), ColumnKind::Other);
+    }
+
+    /// SQLite database in a temporary directory, with its URL
+    async fn sqlite_database(dir: &tempfile::TempDir, schema: &str) -> (String, sqlx::SqlitePool) {
+        let db_url = format!("sqlite://{}?mode=rwc", dir.path().join("dev.db").display());
+        let pool = sqlx::SqlitePool::connect(&db_url).await.unwrap();
+        sqlx::query(schema).execute(&pool).await.unwrap();
+        (db_url, pool)
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db_url, pool) = sqlite_database(
+            &dir,
+            "CREATE TABLE images (id INTEGER PRIMARY KEY, hotel_id INTEGER NOT NULL, url TEXT)",
+        )
+        .await;
+        for id in 1..=5 {
+            let url = if id == 3 {
+                String::new()
+            } else {
+                format!("https://img.example.com/{}.jpg", id)
+            };
+            sqlx::query("INSERT INTO images (id, hotel_id, url) VALUES (?, 7, ?)")
+                .bind(id)
+                .bind(url)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let database = Database::new(
+            &db_url,
+            Table::new("images".into(), vec!["id".into()], "url".into()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(database.get_total_count().await.unwrap(), 4);
+        let batch = database
+            .fetch_batch(Some(&RowKey::from(1)), 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            batch,
+            vec![
+                (RowKey::from(2), "https://img.example.com/2.jpg".to_string()),
+                (RowKey::from(4), "https://img.example.com/4.jpg".to_string())
+            ]
+        );
+        assert_eq!(database.fetch_batch(None, 10).await.unwrap().len(), 4);
+        assert_eq!(
+            database
+                .fetch_url(&RowKey::from(5))
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("https://img.example.com/5.jpg")
+        );
+        assert!(database.fetch_url(&RowKey::parse("5,1")).await.is_err());
+
+        // Deleted rows are kept in the backup table, once
+        let keys = [RowKey::from(2), RowKey::from(4)];
+        assert_eq!(database.delete_by_keys(&keys, true).await.unwrap(), 2);
+        assert_eq!(database.delete_by_keys(&keys, true).await.unwrap(), 0);
+        assert_eq!(database.get_total_count().await.unwrap(), 2);
+        let backup =
+            sqlx::query("SELECT id, hotel_id, deleted_at FROM images_deleted_backup ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(backup.len(), 2);
+        assert_eq!(backup[1].get::<i64, _>("id"), 4);
+        assert_eq!(backup[1].get::<i64, _>("hotel_id"), 7);
+        assert!(backup[1].get::<Option<String>, _>("deleted_at").is_some());
+
+        database.close().await;
+        pool.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_composite_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db_url, pool) = sqlite_database(
+            &dir,
+            "CREATE TABLE images (hotel_id INTEGER, position INTEGER, url TEXT, \
+             PRIMARY KEY (hotel_id, position))",
+        )
+        .await;
+        for (hotel_id, position) in [(1, 1), (1, 2), (2, 1), (2, 2)] {
+            sqlx::query("INSERT INTO images VALUES (?, ?, 'https://img.example.com/a.jpg')")
+                .bind(hotel_id)
+                .bind(position)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let table = Table::new(
+            "images".into(),
+            vec!["hotel_id".into(), "position".into()],
+            "url".into(),
+        );
+        let database = Database::new(&db_url, table).await.unwrap();
+
+        // Keyset pagination continues within a hotel, then with the next one
+        let batch = database
+            .fetch_batch(Some(&RowKey::parse("1,2")), 10)
+            .await
+            .unwrap();
+        let keys: Vec<String> = batch.iter().map(|(key, _)| key.to_string()).collect();
+        assert_eq!(keys, ["(2, 1)", "(2, 2)"]);
+
+        let deleted = database
+            .delete_by_keys(&[RowKey::parse("1,2"), RowKey::parse("2,1")], true)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let remaining: Vec<RowKey> = database
+            .fetch_batch(None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(remaining, [RowKey::parse("1,1"), RowKey::parse("2,2")]);
+
+        database.close().await;
+        pool.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_text_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db_url, pool) = sqlite_database(
+            &dir,
+            "CREATE TABLE images (code TEXT PRIMARY KEY, url TEXT)",
+        )
+        .await;
+        for code in ["img-b", "img-a", "img-c"] {
+            sqlx::query("INSERT INTO images VALUES (?, 'https://img.example.com/a.jpg')")
+                .bind(code)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let database = Database::new(
+            &db_url,
+            Table::new("images".into(), vec!["code".into()], "url".into()),
+        )
+        .await
+        .unwrap();
+        let batch = database
+            .fetch_batch(Some(&RowKey::parse("img-a")), 10)
+            .await
+            .unwrap();
+        let keys: Vec<String> = batch.iter().map(|(key, _)| key.to_string()).collect();
+        assert_eq!(keys, ["img-b", "img-c"]);
+        assert_eq!(
+            database
+                .delete_by_keys(&[RowKey::parse("img-c")], false)
+                .await
+                .unwrap(),
+            1
+        );
+
+        database.close().await;
+        pool.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_catalog_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db_url, pool) = sqlite_database(
+            &dir,
+            r#"CREATE TABLE "HotelImage" ("Id" INTEGER PRIMARY KEY, "ImageUrl" TEXT, data BLOB)"#,
+        )
+        .await;
+        sqlx::query(
+            r#"INSERT INTO "HotelImage" VALUES (1, 'https://img.example.com/1.jpg', x'00')"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let connect = |table: &str, key: &str, url: &str| {
+            Database::new(
+                &db_url,
+                Table::new(table.into(), vec![key.into()], url.into()),
+            )
+        };
+        let error = |result: Result<Database>| result.err().unwrap().to_string();
+
+        // Mixed-case names are used as they are in the catalog
+        let database = connect("hotelimage", "id", "imageurl").await.unwrap();
+        assert_eq!(database.table().name, "HotelImage");
+        assert_eq!(database.table().key_columns, ["Id"]);
+        assert_eq!(database.get_total_count().await.unwrap(), 1);
+        assert_eq!(database.delete_by_keys(&[1.into()], true).await.unwrap(), 1);
+        assert_eq!(database.parse_key("1").unwrap(), RowKey::from(1));
+        assert!(database.parse_key("one").is_err());
+        assert!(database.parse_key("1,2").is_err());
+        database.close().await;
+
+        assert!(error(connect("images", "Id", "ImageUrl").await).contains("does not exist"));
+        assert!(
+            error(connect("HotelImage; DROP TABLE x", "Id", "ImageUrl").await)
+                .contains("does not exist")
+        );
+        assert!(error(connect("HotelImage", "Id", "url").await)
+            .contains(r#"Column "url" does not exist"#));
+        assert!(error(connect("HotelImage", "Id", "data").await).contains("expected a text type"));
+        assert!(error(connect("HotelImage", "data", "ImageUrl").await)
+            .contains("integer, UUID and text keys"));
+
+        // Views can't be backed up or deleted from
+        sqlx::query(r#"CREATE VIEW image_view AS SELECT * FROM "HotelImage""#)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(error(connect("image_view", "Id", "ImageUrl").await).contains("does not exist"));
+
+        pool.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db_url, pool) = sqlite_database(
+            &dir,
+            "CREATE TABLE images (id INTEGER PRIMARY KEY, url TEXT)",
+        )
+        .await;
+        sqlx::query("INSERT INTO images VALUES (1, 'https://img.example.com/1.jpg')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let table = || Table::new("images".into(), vec!["id".into()], "url".into());
+
+        // Without a schema the table is looked up in the default one, and qualified after
+        let database = Database::new(&db_url, table()).await.unwrap();
+        assert_eq!(database.table().qualified_name(), "main.images");
+        assert_eq!(
+            database.table().qualified_backup_name(),
+            "main.images_deleted_backup"
+        );
+        database.close().await;
+
+        let database = Database::new(&db_url, table().with_schema(Some("MAIN".into())))
+            .await
+            .unwrap();
+        assert_eq!(database.table().schema.as_deref(), Some("main"));
+        assert_eq!(database.delete_by_keys(&[1.into()], true).await.unwrap(), 1);
+        let backed_up: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM main.images_deleted_backup")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(backed_up, 1);
+        database.close().await;
+
+        let error = Database::new(&db_url, table().with_schema(Some("tenant_a".into())))
+            .await
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains(r#""tenant_a.images" does not exist"#));
+
+        pool.close().await;
+    }
 }
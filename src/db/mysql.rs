@@ -0,0 +1,280 @@
+use super::{backup_columns, Backend, ColumnInfo, Dialect, Table, TableName};
+use crate::key::{KeyValue, RowKey};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use sqlx::mysql::{MySqlArguments, MySqlPoolOptions, MySqlRow};
+use sqlx::query::Query;
+use sqlx::{Column, MySql, MySqlPool, Row};
+
+/// MySQL (or MariaDB) database holding the image table
+pub struct MySqlBackend {
+    pool: MySqlPool,
+    table: Table,
+}
+
+const DIALECT: Dialect = Dialect::MySql;
+
+impl MySqlBackend {
+    /// Connect and check the table against the catalog
+    pub async fn connect(db_url: &str, table: Table) -> Result<Self> {
+        let pool = MySqlPoolOptions::new()
+            .max_connections(10)
+            .connect(db_url)
+            .await?;
+
+        let mut backend = Self { pool, table };
+        backend.table = super::resolve_table(&backend, DIALECT, &backend.table).await?;
+        Ok(backend)
+    }
+}
+
+/// Bind the values of a key, in column order (UUIDs as text)
+fn bind_key<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    key: &RowKey,
+) -> Query<'q, MySql, MySqlArguments> {
+    for value in key.values() {
+        query = match value {
+            KeyValue::Int(int) => query.bind(*int),
+            KeyValue::Uuid(uuid) => query.bind(uuid.to_string()),
+            KeyValue::Text(text) => query.bind(text.clone()),
+        };
+    }
+    query
+}
+
+/// Key of a row whose first `width` columns are the key columns
+fn row_key(row: &MySqlRow, width: usize) -> Result<RowKey> {
+    let values = (0..width)
+        .map(|index| {
+            if let Ok(int) = row.try_get::<i64, _>(index) {
+                Ok(KeyValue::Int(int))
+            } else if let Some(int) = row
+                .try_get::<u64, _>(index)
+                .ok()
+                .and_then(|int| i64::try_from(int).ok())
+            {
+                Ok(KeyValue::Int(int))
+            } else if let Ok(text) = row.try_get::<String, _>(index) {
+                Ok(KeyValue::Text(text))
+            } else {
+                Err(anyhow!(
+                    "Key column {} must be an integer or text",
+                    row.column(index).name()
+                ))
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok(RowKey::new(values))
+}
+
+impl Backend for MySqlBackend {
+    fn table(&self) -> &Table {
+        &self.table
+    }
+
+    fn find_tables<'a>(
+        &'a self,
+        schema: Option<&'a str>,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<TableName>>> {
+        Box::pin(async move {
+            // A schema is a database; the connection's own without one
+            let rows = sqlx::query(
+                "SELECT CAST(table_schema AS CHAR), CAST(table_name AS CHAR) \
+                 FROM information_schema.tables \
+                 WHERE table_type = 'BASE TABLE' \
+                 AND LOWER(table_schema) = LOWER(COALESCE(?, DATABASE())) \
+                 AND LOWER(table_name) = LOWER(?) ORDER BY table_schema, table_name",
+            )
+            .bind(schema)
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| TableName {
+                    schema: row.get(0),
+                    name: row.get(1),
+                })
+                .collect())
+        })
+    }
+
+    fn columns<'a>(
+        &'a self,
+        schema: &'a str,
+        table: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ColumnInfo>>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT CAST(column_name AS CHAR), CAST(column_type AS CHAR) \
+                 FROM information_schema.columns \
+                 WHERE table_schema = ? AND table_name = ? ORDER BY ordinal_position",
+            )
+            .bind(schema)
+            .bind(table)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| ColumnInfo {
+                    name: row.get(0),
+                    data_type: row.get(1),
+                })
+                .collect())
+        })
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<i64>> {
+        Box::pin(async move {
+            let url_column = self.table.sql_url_column(DIALECT);
+            let query = format!(
+                "SELECT COUNT(*) as count FROM {} WHERE {} IS NOT NULL AND {} != ''",
+                self.table.sql_name(DIALECT),
+                url_column,
+                url_column
+            );
+
+            let row = sqlx::query(&query).fetch_one(&self.pool).await?;
+            Ok(row.get("count"))
+        })
+    }
+
+    fn fetch_url<'a>(&'a self, key: &'a RowKey) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async move {
+            let query = format!(
+                "SELECT {} FROM {} WHERE {}",
+                self.table.sql_url_column(DIALECT),
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, 1)
+            );
+
+            let row = bind_key(sqlx::query(&query), key)
+                .fetch_optional(&self.pool)
+                .await?;
+
+            Ok(row.and_then(|row| row.get::<Option<String>, _>(0)))
+        })
+    }
+
+    fn fetch_batch<'a>(
+        &'a self,
+        after: Option<&'a RowKey>,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<(RowKey, String)>>> {
+        Box::pin(async move {
+            let name = self.table.sql_name(DIALECT);
+            let url_column = self.table.sql_url_column(DIALECT);
+            let width = self.table.key_columns.len();
+            let after_condition = match after {
+                Some(_) => format!("{} AND ", self.table.keys_after(DIALECT, 1)),
+                None => String::new(),
+            };
+            let query = format!(
+                "SELECT {keys}, {url_column} FROM {name} WHERE {after_condition}{url_column} IS NOT NULL AND {url_column} != '' ORDER BY {keys} LIMIT ?",
+                keys = self.table.key_list(DIALECT),
+            );
+
+            let mut query = sqlx::query(&query);
+            if let Some(key) = after {
+                query = bind_key(query, key);
+            }
+            let rows = query.bind(limit).fetch_all(&self.pool).await?;
+
+            rows.iter()
+                .map(|row| Ok((row_key(row, width)?, row.get(width))))
+                .collect()
+        })
+    }
+
+    fn ensure_backup_table(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            if let (true, Some(schema)) =
+                (self.table.backup_elsewhere(), self.table.backup_schema())
+            {
+                let query = format!("CREATE SCHEMA IF NOT EXISTS {}", DIALECT.quote(schema));
+                sqlx::query(&query).execute(&self.pool).await?;
+            }
+
+            // Same structure and keys, then the deleted_at timestamp
+            let backup_table = self.table.sql_backup_name(DIALECT);
+            let query = format!(
+                "CREATE TABLE IF NOT EXISTS {} LIKE {}",
+                backup_table,
+                self.table.sql_name(DIALECT)
+            );
+            sqlx::query(&query).execute(&self.pool).await?;
+
+            // MySQL has no ADD COLUMN IF NOT EXISTS
+            if !self
+                .columns(
+                    self.table.backup_schema().unwrap_or_default(),
+                    &self.table.backup_name(),
+                )
+                .await?
+                .iter()
+                .any(|column| column.name == "deleted_at")
+            {
+                let query = format!(
+                    "ALTER TABLE {} ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP",
+                    backup_table
+                );
+                sqlx::query(&query).execute(&self.pool).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn backup<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let columns = backup_columns(
+                DIALECT,
+                &self
+                    .columns(self.table.checked_schema(), &self.table.name)
+                    .await?,
+            );
+
+            let insert_query = format!(
+                "INSERT IGNORE INTO {} ({}) SELECT {} FROM {} WHERE {}",
+                self.table.sql_backup_name(DIALECT),
+                columns,
+                columns,
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&insert_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn delete<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let delete_query = format!(
+                "DELETE FROM {} WHERE {}",
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&delete_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.pool.close())
+    }
+}
@@ -0,0 +1,288 @@
+use super::{backup_columns, Backend, ColumnInfo, Dialect, Table, TableName};
+use crate::key::{KeyValue, RowKey};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use sqlx::postgres::{PgArguments, PgPoolOptions, PgRow};
+use sqlx::query::Query;
+use sqlx::{Column, PgPool, Postgres, Row};
+use uuid::Uuid;
+
+/// PostgreSQL database holding the image table
+pub struct PostgresBackend {
+    pool: PgPool,
+    table: Table,
+}
+
+const DIALECT: Dialect = Dialect::Postgres;
+
+impl PostgresBackend {
+    /// Connect and check the table against the catalog
+    pub async fn connect(db_url: &str, table: Table) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .connect(db_url)
+            .await?;
+
+        let mut backend = Self { pool, table };
+        backend.table = super::resolve_table(&backend, DIALECT, &backend.table).await?;
+        Ok(backend)
+    }
+}
+
+/// Bind the values of a key, in column order
+fn bind_key<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    key: &RowKey,
+) -> Query<'q, Postgres, PgArguments> {
+    for value in key.values() {
+        query = match value {
+            KeyValue::Int(int) => query.bind(*int),
+            KeyValue::Uuid(uuid) => query.bind(*uuid),
+            KeyValue::Text(text) => query.bind(text.clone()),
+        };
+    }
+    query
+}
+
+/// Key of a row whose first `width` columns are the key columns
+fn row_key(row: &PgRow, width: usize) -> Result<RowKey> {
+    let values = (0..width)
+        .map(|index| {
+            if let Ok(int) = row.try_get::<i64, _>(index) {
+                Ok(KeyValue::Int(int))
+            } else if let Ok(int) = row.try_get::<i32, _>(index) {
+                Ok(KeyValue::Int(int.into()))
+            } else if let Ok(int) = row.try_get::<i16, _>(index) {
+                Ok(KeyValue::Int(int.into()))
+            } else if let Ok(uuid) = row.try_get::<Uuid, _>(index) {
+                Ok(KeyValue::Uuid(uuid))
+            } else if let Ok(text) = row.try_get::<String, _>(index) {
+                Ok(KeyValue::Text(text))
+            } else {
+                Err(anyhow!(
+                    "Key column {} must be an integer, UUID or text",
+                    row.column(index).name()
+                ))
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok(RowKey::new(values))
+}
+
+impl Backend for PostgresBackend {
+    fn table(&self) -> &Table {
+        &self.table
+    }
+
+    fn find_tables<'a>(
+        &'a self,
+        schema: Option<&'a str>,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<TableName>>> {
+        Box::pin(async move {
+            // Tables (or partitioned tables) in the schema, or else on the search path; views
+            // and foreign tables can't be backed up or deleted from
+            let rows = sqlx::query(
+                "SELECT n.nspname::text, c.relname::text FROM pg_catalog.pg_class c \
+                 JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+                 WHERE c.relkind IN ('r', 'p') AND lower(c.relname) = lower($2) \
+                 AND CASE WHEN $1::text IS NULL THEN pg_catalog.pg_table_is_visible(c.oid) \
+                 ELSE lower(n.nspname) = lower($1) END \
+                 ORDER BY n.nspname, c.relname",
+            )
+            .bind(schema)
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| TableName {
+                    schema: row.get(0),
+                    name: row.get(1),
+                })
+                .collect())
+        })
+    }
+
+    fn columns<'a>(
+        &'a self,
+        schema: &'a str,
+        table: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ColumnInfo>>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT a.attname::text, pg_catalog.format_type(a.atttypid, a.atttypmod) \
+                 FROM pg_catalog.pg_attribute a \
+                 JOIN pg_catalog.pg_class c ON c.oid = a.attrelid \
+                 JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+                 WHERE n.nspname = $1 AND c.relname = $2 AND a.attnum > 0 AND NOT a.attisdropped \
+                 ORDER BY a.attnum",
+            )
+            .bind(schema)
+            .bind(table)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| ColumnInfo {
+                    name: row.get(0),
+                    data_type: row.get(1),
+                })
+                .collect())
+        })
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<i64>> {
+        Box::pin(async move {
+            let url_column = self.table.sql_url_column(DIALECT);
+            let query = format!(
+                "SELECT COUNT(*) as count FROM {} WHERE {} IS NOT NULL AND {} != ''",
+                self.table.sql_name(DIALECT),
+                url_column,
+                url_column
+            );
+
+            let row = sqlx::query(&query).fetch_one(&self.pool).await?;
+            Ok(row.get("count"))
+        })
+    }
+
+    fn fetch_url<'a>(&'a self, key: &'a RowKey) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async move {
+            let query = format!(
+                "SELECT {} FROM {} WHERE {}",
+                self.table.sql_url_column(DIALECT),
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, 1)
+            );
+
+            let row = bind_key(sqlx::query(&query), key)
+                .fetch_optional(&self.pool)
+                .await?;
+
+            Ok(row.and_then(|row| row.get::<Option<String>, _>(0)))
+        })
+    }
+
+    fn fetch_batch<'a>(
+        &'a self,
+        after: Option<&'a RowKey>,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<(RowKey, String)>>> {
+        Box::pin(async move {
+            let name = self.table.sql_name(DIALECT);
+            let url_column = self.table.sql_url_column(DIALECT);
+            let width = self.table.key_columns.len();
+            let after_condition = match after {
+                Some(_) => format!("{} AND ", self.table.keys_after(DIALECT, 1)),
+                None => String::new(),
+            };
+            let query = format!(
+                "SELECT {keys}, {url_column} FROM {name} WHERE {after_condition}{url_column} IS NOT NULL AND {url_column} != '' ORDER BY {keys} LIMIT {limit}",
+                keys = self.table.key_list(DIALECT),
+                limit = DIALECT.param(1 + after.map_or(0, |_| width)),
+            );
+
+            let mut query = sqlx::query(&query);
+            if let Some(key) = after {
+                query = bind_key(query, key);
+            }
+            let rows = query.bind(limit).fetch_all(&self.pool).await?;
+
+            rows.iter()
+                .map(|row| Ok((row_key(row, width)?, row.get(width))))
+                .collect()
+        })
+    }
+
+    fn ensure_backup_table(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            if let (true, Some(schema)) =
+                (self.table.backup_elsewhere(), self.table.backup_schema())
+            {
+                let query = format!("CREATE SCHEMA IF NOT EXISTS {}", DIALECT.quote(schema));
+                if let Err(error) = sqlx::query(&query).execute(&self.pool).await {
+                    // IF NOT EXISTS still fails when another connection (e.g. the backup of
+                    // another tenant) creates the schema at the same time
+                    let exists: bool = sqlx::query_scalar(
+                        "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_namespace WHERE nspname = $1)",
+                    )
+                    .bind(schema)
+                    .fetch_one(&self.pool)
+                    .await?;
+                    if !exists {
+                        return Err(error.into());
+                    }
+                }
+            }
+
+            // Same structure + deleted_at timestamp
+            let query = format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    LIKE {} INCLUDING ALL,
+                    deleted_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+                )
+                "#,
+                self.table.sql_backup_name(DIALECT),
+                self.table.sql_name(DIALECT)
+            );
+
+            sqlx::query(&query).execute(&self.pool).await?;
+            Ok(())
+        })
+    }
+
+    fn backup<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            // Columns of the original table (deleted_at is filled by its default)
+            let columns = backup_columns(
+                DIALECT,
+                &self
+                    .columns(self.table.checked_schema(), &self.table.name)
+                    .await?,
+            );
+
+            let insert_query = format!(
+                "INSERT INTO {} ({}) SELECT {} FROM {} WHERE {} ON CONFLICT DO NOTHING",
+                self.table.sql_backup_name(DIALECT),
+                columns,
+                columns,
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&insert_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn delete<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let delete_query = format!(
+                "DELETE FROM {} WHERE {}",
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&delete_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.pool.close())
+    }
+}
@@ -0,0 +1,289 @@
+use super::{backup_columns, Backend, ColumnInfo, Dialect, Table, TableName};
+use crate::key::{KeyValue, RowKey};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqlitePoolOptions, SqliteRow};
+use sqlx::{Column, Row, Sqlite, SqlitePool};
+
+/// SQLite database file holding the image table
+pub struct SqliteBackend {
+    pool: SqlitePool,
+    table: Table,
+}
+
+/// Column of a table, from `PRAGMA table_info`
+struct TableColumn {
+    name: String,
+    declared_type: String,
+    /// Position in the primary key (0 if not part of it)
+    primary_key: i64,
+}
+
+const DIALECT: Dialect = Dialect::Sqlite;
+
+impl SqliteBackend {
+    /// Connect and check the table against the catalog
+    pub async fn connect(db_url: &str, table: Table) -> Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect(db_url)
+            .await?;
+
+        let mut backend = Self { pool, table };
+        backend.table = super::resolve_table(&backend, DIALECT, &backend.table).await?;
+        Ok(backend)
+    }
+
+    async fn table_info(&self, schema: &str, table: &str) -> Result<Vec<TableColumn>> {
+        let rows = sqlx::query("SELECT name, type, pk FROM pragma_table_info(?, ?) ORDER BY cid")
+            .bind(table)
+            .bind(schema)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TableColumn {
+                name: row.get(0),
+                declared_type: row.get(1),
+                primary_key: row.get(2),
+            })
+            .collect())
+    }
+}
+
+/// Bind the values of a key, in column order (UUIDs as text)
+fn bind_key<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    key: &RowKey,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    for value in key.values() {
+        query = match value {
+            KeyValue::Int(int) => query.bind(*int),
+            KeyValue::Uuid(uuid) => query.bind(uuid.to_string()),
+            KeyValue::Text(text) => query.bind(text.clone()),
+        };
+    }
+    query
+}
+
+/// Key of a row whose first `width` columns are the key columns
+fn row_key(row: &SqliteRow, width: usize) -> Result<RowKey> {
+    let values = (0..width)
+        .map(|index| {
+            if let Ok(int) = row.try_get::<i64, _>(index) {
+                Ok(KeyValue::Int(int))
+            } else if let Ok(text) = row.try_get::<String, _>(index) {
+                Ok(KeyValue::Text(text))
+            } else {
+                Err(anyhow!(
+                    "Key column {} must be an integer or text",
+                    row.column(index).name()
+                ))
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok(RowKey::new(values))
+}
+
+impl Backend for SqliteBackend {
+    fn table(&self) -> &Table {
+        &self.table
+    }
+
+    fn find_tables<'a>(
+        &'a self,
+        schema: Option<&'a str>,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<TableName>>> {
+        Box::pin(async move {
+            // Schemas are the database's attached databases ("main" without one)
+            let schemas: Vec<String> = sqlx::query("SELECT name FROM pragma_database_list")
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|row| row.get(0))
+                .filter(|found: &String| found.eq_ignore_ascii_case(schema.unwrap_or("main")))
+                .collect();
+
+            let mut tables = Vec::new();
+            for schema in schemas {
+                let query = format!(
+                    "SELECT name FROM {}.sqlite_master WHERE type = 'table' \
+                     AND lower(name) = lower(?) ORDER BY name",
+                    DIALECT.quote(&schema)
+                );
+                let rows = sqlx::query(&query).bind(name).fetch_all(&self.pool).await?;
+                tables.extend(rows.into_iter().map(|row| TableName {
+                    schema: schema.clone(),
+                    name: row.get(0),
+                }));
+            }
+            Ok(tables)
+        })
+    }
+
+    fn columns<'a>(
+        &'a self,
+        schema: &'a str,
+        table: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ColumnInfo>>> {
+        Box::pin(async move {
+            Ok(self
+                .table_info(schema, table)
+                .await?
+                .into_iter()
+                .map(|column| ColumnInfo {
+                    name: column.name,
+                    data_type: column.declared_type,
+                })
+                .collect())
+        })
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<i64>> {
+        Box::pin(async move {
+            let url_column = self.table.sql_url_column(DIALECT);
+            let query = format!(
+                "SELECT COUNT(*) as count FROM {} WHERE {} IS NOT NULL AND {} != ''",
+                self.table.sql_name(DIALECT),
+                url_column,
+                url_column
+            );
+
+            let row = sqlx::query(&query).fetch_one(&self.pool).await?;
+            Ok(row.get("count"))
+        })
+    }
+
+    fn fetch_url<'a>(&'a self, key: &'a RowKey) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async move {
+            let query = format!(
+                "SELECT {} FROM {} WHERE {}",
+                self.table.sql_url_column(DIALECT),
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, 1)
+            );
+
+            let row = bind_key(sqlx::query(&query), key)
+                .fetch_optional(&self.pool)
+                .await?;
+
+            Ok(row.and_then(|row| row.get::<Option<String>, _>(0)))
+        })
+    }
+
+    fn fetch_batch<'a>(
+        &'a self,
+        after: Option<&'a RowKey>,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<(RowKey, String)>>> {
+        Box::pin(async move {
+            let name = self.table.sql_name(DIALECT);
+            let url_column = self.table.sql_url_column(DIALECT);
+            let width = self.table.key_columns.len();
+            let after_condition = match after {
+                Some(_) => format!("{} AND ", self.table.keys_after(DIALECT, 1)),
+                None => String::new(),
+            };
+            let query = format!(
+                "SELECT {keys}, {url_column} FROM {name} WHERE {after_condition}{url_column} IS NOT NULL AND {url_column} != '' ORDER BY {keys} LIMIT ?",
+                keys = self.table.key_list(DIALECT),
+            );
+
+            let mut query = sqlx::query(&query);
+            if let Some(key) = after {
+                query = bind_key(query, key);
+            }
+            let rows = query.bind(limit).fetch_all(&self.pool).await?;
+
+            rows.iter()
+                .map(|row| Ok((row_key(row, width)?, row.get(width))))
+                .collect()
+        })
+    }
+
+    fn ensure_backup_table(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // SQLite has no CREATE TABLE ... LIKE: rebuild the columns and primary key
+            let mut columns = self
+                .table_info(self.table.checked_schema(), &self.table.name)
+                .await?;
+            columns.retain(|column| column.name != "deleted_at");
+            let mut definitions: Vec<String> = columns
+                .iter()
+                .map(|column| format!("{} {}", DIALECT.quote(&column.name), column.declared_type))
+                .collect();
+            definitions.push("deleted_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP".to_string());
+
+            columns.sort_by_key(|column| column.primary_key);
+            let primary_key: Vec<String> = columns
+                .iter()
+                .filter(|column| column.primary_key > 0)
+                .map(|column| DIALECT.quote(&column.name))
+                .collect();
+            if !primary_key.is_empty() {
+                definitions.push(format!("PRIMARY KEY ({})", primary_key.join(", ")));
+            }
+
+            let query = format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                self.table.sql_backup_name(DIALECT),
+                definitions.join(", ")
+            );
+            sqlx::query(&query).execute(&self.pool).await?;
+            Ok(())
+        })
+    }
+
+    fn backup<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let columns = backup_columns(
+                DIALECT,
+                &self
+                    .columns(self.table.checked_schema(), &self.table.name)
+                    .await?,
+            );
+
+            let insert_query = format!(
+                "INSERT OR IGNORE INTO {} ({}) SELECT {} FROM {} WHERE {}",
+                self.table.sql_backup_name(DIALECT),
+                columns,
+                columns,
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&insert_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn delete<'a>(&'a self, keys: &'a [RowKey]) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let delete_query = format!(
+                "DELETE FROM {} WHERE {}",
+                self.table.sql_name(DIALECT),
+                self.table.keys_in(DIALECT, keys.len())
+            );
+
+            let mut query = sqlx::query(&delete_query);
+            for key in keys {
+                query = bind_key(query, key);
+            }
+            let result = query.execute(&self.pool).await?;
+
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.pool.close())
+    }
+}
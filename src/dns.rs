@@ -0,0 +1,155 @@
+use crate::policy::NetworkPolicy;
+use hickory_resolver::config::ResolverConfig;
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::{Resolver, TokioResolver};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Result of resolving an image host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lookup {
+    /// Host resolved to these addresses
+    Resolved(Vec<IpAddr>),
+    /// The domain does not exist (NXDOMAIN)
+    NotFound,
+    /// Lookup failed for a reason that may be temporary (timeout, SERVFAIL)
+    Failed(String),
+}
+
+/// DNS resolver shared by all clients of a checker
+///
+/// Positive answers are cached by the resolver according to their TTL. Domains that
+/// don't exist are remembered for the rest of the run, so the remaining URLs of a
+/// deregistered provider don't each wait on DNS.
+#[derive(Clone)]
+pub struct DnsCache {
+    resolver: TokioResolver,
+    dead_domains: Arc<Mutex<BTreeSet<String>>>,
+    /// Policy resolved addresses must pass before a connection is made
+    policy: Arc<NetworkPolicy>,
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DnsCache {
+    /// Create a resolver using the system configuration (falling back to public resolvers)
+    pub fn new() -> Self {
+        let resolver = match Resolver::builder_tokio() {
+            Ok(builder) => builder.build(),
+            Err(e) => {
+                warn!(
+                    "Could not read system DNS configuration ({}), using default resolvers",
+                    e
+                );
+                Resolver::builder_with_config(
+                    ResolverConfig::default(),
+                    TokioConnectionProvider::default(),
+                )
+                .build()
+            }
+        };
+
+        Self {
+            resolver,
+            dead_domains: Arc::new(Mutex::new(BTreeSet::new())),
+            policy: Arc::new(NetworkPolicy::default()),
+        }
+    }
+
+    /// Apply a network policy to resolved addresses
+    pub fn with_policy(mut self, policy: Arc<NetworkPolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Resolve a domain, remembering it if it does not exist
+    pub async fn lookup(&self, domain: &str) -> Lookup {
+        let domain = normalize(domain);
+        if self.is_dead(&domain) {
+            return Lookup::NotFound;
+        }
+
+        match self.resolver.lookup_ip(domain.as_str()).await {
+            Ok(lookup) => Lookup::Resolved(lookup.into_iter().collect()),
+            Err(e) if e.is_nx_domain() => {
+                self.mark_dead(&domain);
+                Lookup::NotFound
+            }
+            Err(e) => Lookup::Failed(e.to_string()),
+        }
+    }
+
+    /// Whether a domain is already known not to exist
+    pub fn is_dead(&self, domain: &str) -> bool {
+        self.dead_domains.lock().unwrap().contains(domain)
+    }
+
+    /// Remember a domain as not existing
+    pub fn mark_dead(&self, domain: &str) {
+        if self.dead_domains.lock().unwrap().insert(domain.to_string()) {
+            info!(
+                "Domain {} does not exist, skipping its remaining URLs",
+                domain
+            );
+        }
+    }
+}
+
+/// Key of a domain in the cache: lowercase, without the trailing dot of absolute names
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.clone();
+        Box::pin(async move {
+            let lookup = cache
+                .resolver
+                .lookup_ip(name.as_str())
+                .await
+                .inspect_err(|e| {
+                    if e.is_nx_domain() {
+                        cache.mark_dead(&normalize(name.as_str()));
+                    }
+                })?;
+
+            // Refuse the host if any of its addresses is blocked, so that a mix of public
+            // and internal records can't be used to reach internal services
+            let ips: Vec<_> = lookup.into_iter().collect();
+            if let Some(blocked) = ips.iter().find_map(|ip| cache.policy.check(*ip)) {
+                return Err(blocked.into());
+            }
+
+            let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dead_domain_is_remembered() {
+        let dns = DnsCache::new();
+        dns.mark_dead("gone-supplier.example");
+
+        assert_eq!(dns.lookup("Gone-Supplier.example.").await, Lookup::NotFound);
+        assert!(dns.is_dead("gone-supplier.example"));
+        assert!(!dns.is_dead("supplier.example"));
+
+        // Absolute names share the key of the relative ones
+        assert_eq!(normalize("CDN.Example."), "cdn.example");
+    }
+}
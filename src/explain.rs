@@ -0,0 +1,111 @@
+use crate::config::{Args, ExplainArgs};
+use anyhow::{anyhow, Result};
+use hotel_broken_image_checker::checker::CheckResult;
+use hotel_broken_image_checker::db::Database;
+use hotel_broken_image_checker::key::RowKey;
+use hotel_broken_image_checker::trace::{self, Exchange, TraceEvent};
+use reqwest::header::HeaderMap;
+
+/// Check one URL (given directly or by row id) and print how it was classified
+pub async fn run(args: &Args, explain: &ExplainArgs) -> Result<()> {
+    let (key, url) = match (&explain.url, &explain.id) {
+        (Some(url), _) => (None, url.clone()),
+        (None, Some(id)) => {
+            let database = Database::new(&args.get_db_url()?, args.db_table()?).await?;
+            let key = database.parse_key(id)?;
+            let url = database.fetch_url(&key).await?.ok_or_else(|| {
+                let table = database.table();
+                anyhow!(
+                    "No URL found for ({}) = {} in {}",
+                    table.key_columns.join(", "),
+                    key,
+                    table.qualified_name()
+                )
+            })?;
+            database.close().await;
+            (Some(key), url)
+        }
+        (None, None) => return Err(anyhow!("Either a URL or --id must be given")),
+    };
+
+    let checker = crate::build_checker(args)?;
+    let id = key.as_ref().and_then(RowKey::as_int).unwrap_or_default();
+    let (result, events) = checker.explain(id, &url).await;
+    if let Some(cassette) = checker.cassette() {
+        cassette.save()?;
+    }
+
+    match key {
+        Some(key) => println!("Explaining [ID: {}] {}", key, url),
+        None => println!("Explaining {}", url),
+    }
+    println!();
+    for event in &events {
+        match event {
+            TraceEvent::Step(step) => println!("  * {}", step),
+            TraceEvent::Exchange(exchange) => print_exchange(exchange),
+        }
+    }
+    println!();
+    print_result(&result, crate::should_delete(args, &result));
+
+    Ok(())
+}
+
+fn print_exchange(exchange: &Exchange) {
+    println!(
+        "  > {} {} ({})",
+        exchange.method,
+        exchange.url,
+        exchange.started_at.format("%H:%M:%S%.3f")
+    );
+    print_headers(&exchange.request_headers);
+
+    match (exchange.status, &exchange.error) {
+        (Some(status), _) => {
+            println!("  < HTTP {} after {:?}", status, exchange.elapsed);
+            print_headers(&exchange.response_headers);
+            if let Some(body) = &exchange.body_prefix {
+                println!("  < first {} bytes: {}", body.len(), trace::sniff(body));
+            }
+        }
+        (None, Some(error)) => println!("  < failed after {:?}: {}", exchange.elapsed, error),
+        (None, None) => println!("  < no response after {:?}", exchange.elapsed),
+    }
+}
+
+/// Print headers, hiding credentials
+fn print_headers(headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = if trace::is_sensitive(name) {
+            "<redacted>"
+        } else {
+            value.to_str().unwrap_or("<binary>")
+        };
+        println!("      {}: {}", name, value);
+    }
+}
+
+fn print_result(result: &CheckResult, deleted: bool) {
+    println!("Result:");
+    println!("  category:  {}", result.outcome.as_str());
+    println!("  valid:     {}", result.is_valid);
+    if let Some(status) = result.status_code {
+        println!("  status:    {}", status);
+    }
+    if let Some(error) = &result.error {
+        println!("  error:     {}", error);
+    }
+    println!("  retryable: {}", result.is_retryable);
+    for (key, value) in &result.annotations {
+        println!("  {:<10} {}", format!("{}:", key), value);
+    }
+    println!(
+        "  --delete:  {}",
+        if deleted {
+            "would delete this row"
+        } else {
+            "would keep this row"
+        }
+    );
+}
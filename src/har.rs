@@ -0,0 +1,316 @@
+use crate::checker::CheckResult;
+use crate::trace::{self, Exchange};
+use anyhow::Result;
+use base64::Engine;
+use chrono::SecondsFormat;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes the requests of broken and retried URLs as a HAR 1.2 file
+///
+/// Entries are written as results come in, so a large run isn't held in memory.
+pub struct HarWriter {
+    writer: BufWriter<File>,
+    entries: usize,
+}
+
+impl HarWriter {
+    /// Create the file and write the log header
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let creator = json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        write!(
+            writer,
+            "{{\"log\":{{\"version\":\"1.2\",\"creator\":{},\"entries\":[",
+            creator
+        )?;
+        Ok(Self { writer, entries: 0 })
+    }
+
+    /// Whether a result's requests belong in the export
+    pub fn is_evidence(result: &CheckResult) -> bool {
+        !result.is_valid || result.was_retried()
+    }
+
+    /// Write one entry per request of a result (redirect hops and retries included)
+    pub fn write_result(&mut self, result: &CheckResult) -> Result<()> {
+        for exchange in &result.exchanges {
+            let separator = if self.entries == 0 { "\n" } else { ",\n" };
+            write!(self.writer, "{}{}", separator, entry(result, exchange))?;
+            self.entries += 1;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Close the log, returning the number of entries written
+    pub fn finish(mut self) -> Result<usize> {
+        write!(self.writer, "\n]}}}}\n")?;
+        self.writer.flush()?;
+        Ok(self.entries)
+    }
+}
+
+/// HAR entry for one request
+fn entry(result: &CheckResult, exchange: &Exchange) -> Value {
+    let time = exchange.elapsed.as_secs_f64() * 1000.0;
+    let http_version = exchange
+        .http_version
+        .map(|version| format!("{:?}", version))
+        .unwrap_or_default();
+    let status_text = exchange
+        .status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or_default();
+
+    let mut response = json!({
+        "status": exchange.status.unwrap_or(0),
+        "statusText": status_text,
+        "httpVersion": http_version,
+        "cookies": [],
+        "headers": headers(&exchange.response_headers),
+        "content": content(exchange),
+        "redirectURL": header(&exchange.response_headers, LOCATION.as_str()).unwrap_or_default(),
+        "headersSize": -1,
+        "bodySize": -1,
+    });
+    if let Some(error) = &exchange.error {
+        response["_error"] = json!(error);
+    }
+
+    json!({
+        "startedDateTime": exchange.started_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "time": time,
+        "request": {
+            "method": exchange.method.as_str(),
+            "url": exchange.url,
+            "httpVersion": http_version,
+            "cookies": [],
+            "headers": headers(&exchange.request_headers),
+            "queryString": query_string(&exchange.url),
+            "headersSize": -1,
+            "bodySize": 0,
+        },
+        "response": response,
+        "cache": {},
+        "timings": {
+            "send": 0,
+            "wait": time,
+            "receive": 0,
+        },
+        "comment": format!(
+            "Row {}: {} (attempt {})",
+            result.id,
+            result.outcome.as_str(),
+            exchange.attempt + 1
+        ),
+        "_rowId": result.id,
+        "_category": result.outcome.as_str(),
+        "_error": result.error,
+        "_attempt": exchange.attempt,
+    })
+}
+
+/// Header list, hiding credentials
+fn headers(headers: &HeaderMap) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if trace::is_sensitive(name) {
+                "<redacted>".into()
+            } else {
+                String::from_utf8_lossy(value.as_bytes())
+            };
+            json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn query_string(url: &str) -> Value {
+    let Ok(url) = Url::parse(url) else {
+        return json!([]);
+    };
+    url.query_pairs()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+/// Response body as far as it was read, base64-encoded since it is usually binary
+fn content(exchange: &Exchange) -> Value {
+    let mime_type = header(&exchange.response_headers, CONTENT_TYPE.as_str()).unwrap_or_default();
+    let Some(body) = &exchange.body_prefix else {
+        return json!({
+            "size": 0,
+            "mimeType": mime_type,
+            "comment": "body not read",
+        });
+    };
+
+    let size = header(&exchange.response_headers, CONTENT_LENGTH.as_str())
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(body.len());
+    json!({
+        "size": size,
+        "mimeType": mime_type,
+        "text": base64::engine::general_purpose::STANDARD.encode(body),
+        "encoding": "base64",
+        "comment": format!("first {} bytes ({})", body.len(), trace::sniff(body)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Outcome;
+    use chrono::Utc;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use reqwest::Method;
+    use std::time::Duration;
+
+    fn exchange(url: &str, status: u16, attempt: u32) -> Exchange {
+        Exchange {
+            attempt,
+            method: Method::GET,
+            url: url.to_string(),
+            request_headers: HeaderMap::new(),
+            started_at: Utc::now(),
+            elapsed: Duration::from_millis(120),
+            status: Some(status),
+            http_version: Some(reqwest::Version::HTTP_11),
+            response_headers: HeaderMap::new(),
+            body_prefix: None,
+            error: None,
+        }
+    }
+
+    fn broken(exchanges: Vec<Exchange>) -> CheckResult {
+        CheckResult {
+            id: 7,
+            url: "https://img.example.com/a.jpg".to_string(),
+            is_valid: false,
+            status_code: Some(404),
+            error: Some("HTTP 404".to_string()),
+            is_retryable: false,
+            outcome: Outcome::Broken,
+            timeout: Some(Duration::from_secs(10)),
+            error_kind: None,
+            exchanges,
+            annotations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut redirect = exchange("https://img.example.com/a.jpg?size=large", 301, 0);
+        redirect
+            .request_headers
+            .insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        redirect.response_headers.insert(
+            LOCATION,
+            HeaderValue::from_static("https://cdn.example.com/a.jpg"),
+        );
+        let result = broken(vec![redirect]);
+
+        let entry = entry(&result, &result.exchanges[0]);
+        assert_eq!(entry["request"]["method"], "GET");
+        assert_eq!(entry["request"]["httpVersion"], "HTTP/1.1");
+        assert_eq!(entry["request"]["headers"][0]["value"], "<redacted>");
+        assert_eq!(entry["request"]["queryString"][0]["value"], "large");
+        assert_eq!(entry["response"]["status"], 301);
+        assert_eq!(entry["response"]["statusText"], "Moved Permanently");
+        assert_eq!(
+            entry["response"]["redirectURL"],
+            "https://cdn.example.com/a.jpg"
+        );
+        assert_eq!(entry["time"], 120.0);
+        assert_eq!(entry["_rowId"], 7);
+        assert_eq!(entry["_category"], "broken");
+    }
+
+    #[test]
+    fn test_content() {
+        let mut page = exchange("https://img.example.com/a.jpg", 404, 1);
+        page.response_headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        page.body_prefix = Some(b"<html>Not found</html>".to_vec());
+
+        let content = content(&page);
+        assert_eq!(content["size"], 22);
+        assert_eq!(content["mimeType"], "text/html");
+        assert_eq!(content["encoding"], "base64");
+        assert_eq!(content["text"], "PGh0bWw+Tm90IGZvdW5kPC9odG1sPg==");
+
+        let failed = Exchange {
+            status: None,
+            http_version: None,
+            error: Some("connection reset".to_string()),
+            ..exchange("https://img.example.com/b.jpg", 0, 0)
+        };
+        let result = broken(vec![failed]);
+        let entry = entry(&result, &result.exchanges[0]);
+        assert_eq!(entry["response"]["status"], 0);
+        assert_eq!(entry["response"]["_error"], "connection reset");
+        assert_eq!(entry["response"]["content"]["size"], 0);
+    }
+
+    #[test]
+    fn test_write_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("evidence.har");
+
+        let mut har = HarWriter::create(&path).unwrap();
+        har.write_result(&broken(vec![
+            exchange("https://img.example.com/a.jpg", 503, 0),
+            exchange("https://img.example.com/a.jpg", 404, 1),
+        ]))
+        .unwrap();
+        har.write_result(&broken(Vec::new())).unwrap();
+        assert_eq!(har.finish().unwrap(), 2);
+
+        let log: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(log["log"]["version"], "1.2");
+        assert_eq!(log["log"]["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(log["log"]["entries"][1]["_attempt"], 1);
+    }
+
+    #[test]
+    fn test_is_evidence() {
+        assert!(HarWriter::is_evidence(&broken(Vec::new())));
+
+        let retried = CheckResult {
+            is_valid: true,
+            outcome: Outcome::Valid,
+            ..broken(vec![
+                exchange("https://img.example.com/a.jpg", 503, 0),
+                exchange("https://img.example.com/a.jpg", 200, 1),
+            ])
+        };
+        assert!(HarWriter::is_evidence(&retried));
+
+        let valid = CheckResult {
+            is_valid: true,
+            outcome: Outcome::Valid,
+            ..broken(vec![exchange("https://img.example.com/a.jpg", 200, 0)])
+        };
+        assert!(!HarWriter::is_evidence(&valid));
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use uuid::Uuid;
+
+/// Value of one key column
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum KeyValue {
+    Int(i64),
+    Uuid(Uuid),
+    Text(String),
+}
+
+impl KeyValue {
+    /// Value typed by its form: an integer, a UUID, or text
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if let Ok(int) = value.parse() {
+            KeyValue::Int(int)
+        } else if let Ok(uuid) = Uuid::parse_str(value) {
+            KeyValue::Uuid(uuid)
+        } else {
+            KeyValue::Text(value.to_string())
+        }
+    }
+}
+
+impl fmt::Display for KeyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyValue::Int(int) => write!(f, "{}", int),
+            KeyValue::Uuid(uuid) => write!(f, "{}", uuid),
+            KeyValue::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+/// Primary key of a row, with one value per key column (several for composite keys)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RowKey(Vec<KeyValue>);
+
+impl RowKey {
+    pub fn new(values: Vec<KeyValue>) -> Self {
+        Self(values)
+    }
+
+    pub fn values(&self) -> &[KeyValue] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The integer of a single-column integer key
+    pub fn as_int(&self) -> Option<i64> {
+        match self.0.as_slice() {
+            [KeyValue::Int(int)] => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// Key given on the command line: comma-separated values for composite keys
+    pub fn parse(key: &str) -> Self {
+        Self(key.split(',').map(KeyValue::parse).collect())
+    }
+}
+
+impl From<i64> for RowKey {
+    fn from(id: i64) -> Self {
+        Self(vec![KeyValue::Int(id)])
+    }
+}
+
+impl fmt::Display for RowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [value] => write!(f, "{}", value),
+            values => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                write!(f, "({})", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Serialized as a plain number for single integer keys, and as a list of typed values
+/// otherwise, so checkpoints written with integer ids keep loading
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RowKeyRepr {
+    Id(i64),
+    Values(Vec<KeyValue>),
+}
+
+impl Serialize for RowKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.as_int() {
+            Some(id) => RowKeyRepr::Id(id),
+            None => RowKeyRepr::Values(self.0.clone()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RowKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match RowKeyRepr::deserialize(deserializer)? {
+            RowKeyRepr::Id(id) => RowKey::from(id),
+            RowKeyRepr::Values(values) => RowKey(values),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(RowKey::parse("42"), RowKey::from(42));
+        assert_eq!(
+            RowKey::parse("7, 3"),
+            RowKey::new(vec![KeyValue::Int(7), KeyValue::Int(3)])
+        );
+        let uuid = "0b6f4c2e-8a1d-4f3b-9c7e-2d5a6b8c9e01";
+        assert_eq!(
+            RowKey::parse(uuid).values(),
+            [KeyValue::Uuid(Uuid::parse_str(uuid).unwrap())]
+        );
+        assert_eq!(
+            RowKey::parse("img-001").values(),
+            [KeyValue::Text("img-001".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(RowKey::from(42).to_string(), "42");
+        assert_eq!(RowKey::parse("7,3").to_string(), "(7, 3)");
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let keys = vec![
+            RowKey::from(42),
+            RowKey::parse("0b6f4c2e-8a1d-4f3b-9c7e-2d5a6b8c9e01"),
+            RowKey::new(vec![KeyValue::Int(7), KeyValue::Text("3".to_string())]),
+        ];
+        let json = serde_json::to_string(&keys).unwrap();
+        assert!(json.starts_with("[42,"));
+        let parsed: Vec<RowKey> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, keys);
+    }
+}
@@ -0,0 +1,36 @@
+//! Detect and clean broken image URLs stored in a database table.
+//!
+//! [`UrlChecker`] checks URLs concurrently with retries, host profiles, proxies and a
+//! network policy; build one with [`UrlChecker::builder`] and consume its results as a
+//! stream with [`UrlChecker::check_stream`]. [`Scan`] reads the URLs from a [`Database`]
+//! table batch by batch, and [`Checkpoint`] records a scan's progress for resuming.
+//! The `broken-image-checker` command line tool is built on this API.
+
+pub mod cassette;
+pub mod chaos;
+pub mod checker;
+pub mod checkpoint;
+pub mod data_uri;
+pub mod db;
+pub mod dns;
+pub mod har;
+pub mod key;
+pub mod mock_server;
+pub mod policy;
+pub mod profiles;
+pub mod proxy;
+pub mod scan;
+pub mod script;
+pub mod signed_url;
+pub mod summary;
+pub mod tenant;
+pub mod tls;
+pub mod trace;
+pub mod validator;
+pub mod waf;
+
+pub use checker::{CheckResult, CheckerBuilder, CheckerConfig, ErrorKind, Outcome, UrlChecker};
+pub use checkpoint::Checkpoint;
+pub use db::Database;
+pub use key::RowKey;
+pub use scan::{Scan, ScanBatch};
@@ -1,17 +1,33 @@
-mod checker;
-mod checkpoint;
+mod bench;
 mod config;
-mod db;
+mod explain;
+mod selftest;
 
-use anyhow::Result;
-use checker::{CheckResult, UrlChecker};
-use checkpoint::Checkpoint;
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use config::Args;
+use config::{Args, Command};
 use csv::Writer;
-use db::Database;
-use indicatif::{ProgressBar, ProgressStyle};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use hotel_broken_image_checker::cassette::Cassette;
+use hotel_broken_image_checker::chaos::FaultInjector;
+use hotel_broken_image_checker::checker::HotlinkRetry;
+use hotel_broken_image_checker::db::Table;
+use hotel_broken_image_checker::har::HarWriter;
+use hotel_broken_image_checker::policy::NetworkPolicy;
+use hotel_broken_image_checker::profiles::HostProfiles;
+use hotel_broken_image_checker::proxy::ProxyPool;
+use hotel_broken_image_checker::script::ClassifyScript;
+use hotel_broken_image_checker::summary::RunSummary;
+use hotel_broken_image_checker::tenant::{discover_tenants, TenantSource};
+use hotel_broken_image_checker::validator::Pipeline;
+use hotel_broken_image_checker::{
+    CheckResult, CheckerBuilder, Checkpoint, Database, Outcome, RowKey, Scan, UrlChecker,
+};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::fs::File;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
@@ -21,7 +37,11 @@ async fn main() -> Result<()> {
     let args = Args::parse();
 
     // Setup logging
-    let log_level = if args.verbose { Level::DEBUG } else { Level::INFO };
+    let log_level = if args.verbose {
+        Level::DEBUG
+    } else {
+        Level::INFO
+    };
     let subscriber = FmtSubscriber::builder()
         .with_max_level(log_level)
         .with_target(false)
@@ -32,204 +52,545 @@ async fn main() -> Result<()> {
     // Validate arguments
     args.validate()?;
 
+    match &args.command {
+        Some(Command::Explain(explain_args)) => return explain::run(&args, explain_args).await,
+        Some(Command::Selftest) => return selftest::run().await,
+        Some(Command::Bench(bench_args)) => return bench::run(&args, bench_args).await,
+        None => {}
+    }
+
     // Get database URL
     let db_url = args.get_db_url()?;
     info!("Database URL configured successfully");
 
-    // Connect to database
-    let database = Database::new(
-        &db_url,
-        args.table.clone(),
-        args.id_column.clone(),
-        args.url_column.clone(),
-    )
-    .await?;
-
-    // Get total count
-    let total_count = database.get_total_count().await?;
-    info!("Total records with URLs: {}", total_count);
-
-    if total_count == 0 {
-        info!("No records to process");
-        return Ok(());
+    let checker = build_checker(&args)?;
+    info!(
+        "URL checker initialized with {} concurrent connections, {} retry attempts ({}s delay, timeout x{} per retry)",
+        args.concurrency, args.retry_attempts, args.retry_delay, args.retry_timeout_multiplier
+    );
+
+    info!("Starting URL check...");
+    if args.dry_run {
+        info!("DRY RUN MODE - No deletions will be performed");
     }
 
-    // Handle resume logic
-    let mut checkpoint = if args.resume && Checkpoint::exists() {
-        match Checkpoint::load().await? {
-            Some(cp) if cp.validate(&args.table, args.dry_run) => {
-                info!(
-                    "Resuming from checkpoint: {}/{} processed, starting from ID {}",
-                    cp.processed, cp.total_records, cp.last_id
-                );
-                cp
-            }
-            Some(_) => {
-                warn!("Checkpoint validation failed, starting fresh");
-                Checkpoint::new(&args.table, total_count, args.dry_run)
-            }
-            None => {
-                warn!("Could not load checkpoint, starting fresh");
-                Checkpoint::new(&args.table, total_count, args.dry_run)
-            }
-        }
-    } else {
-        if args.resume {
-            info!("No checkpoint found, starting fresh");
-        }
-        Checkpoint::new(&args.table, total_count, args.dry_run)
+    let run = Run::new(&args, &db_url, checker);
+    let result = match args.tenant_source() {
+        Some(source) => run.check_tenants(&source).await,
+        None => run.check_table(args.db_table()?, None).await.map(|_| ()),
     };
+    run.finish()?;
 
-    // Create URL checker with retry configuration
-    let checker = UrlChecker::new(
-        args.concurrency,
-        args.timeout,
-        args.retry_attempts,
-        args.retry_delay,
-    )?;
-    info!(
-        "URL checker initialized with {} concurrent connections, {} retry attempts ({}s delay)",
-        args.concurrency, args.retry_attempts, args.retry_delay
-    );
+    result
+}
 
-    // Setup progress bar
-    let pb = ProgressBar::new(total_count as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) | Broken: {msg} | ETA: {eta}")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    pb.set_position(checkpoint.processed as u64);
-    pb.set_message(format!("{}", checkpoint.broken_count));
-
-    // CSV writer for output
-    let mut csv_writer = if let Some(output_path) = &args.output {
-        let file = File::create(output_path)?;
-        let mut writer = Writer::from_writer(file);
-        writer.write_record(["id", "url", "status_code", "error"])?;
-        Some(writer)
-    } else {
-        None
-    };
+/// Checker and outputs shared by the tables of a run (one per tenant schema in
+/// multi-tenant mode)
+struct Run<'a> {
+    args: &'a Args,
+    db_url: &'a str,
+    checker: UrlChecker,
+    csv_writer: Mutex<Option<Writer<File>>>,
+    har_writer: Mutex<Option<HarWriter>>,
+    progress: MultiProgress,
+}
 
-    // Process batches
-    let mut last_id = checkpoint.last_id;
-    let mut all_broken_ids: Vec<i64> = checkpoint.broken_ids.clone();
+/// Totals of one checked table
+#[derive(Default)]
+struct TableReport {
+    processed: i64,
+    broken: i64,
+    summary: RunSummary,
+}
 
-    info!("Starting URL check...");
-    if args.dry_run {
-        info!("DRY RUN MODE - No deletions will be performed");
+impl<'a> Run<'a> {
+    fn new(args: &'a Args, db_url: &'a str, checker: UrlChecker) -> Self {
+        Self {
+            args,
+            db_url,
+            checker,
+            csv_writer: Mutex::new(None),
+            har_writer: Mutex::new(None),
+            progress: MultiProgress::new(),
+        }
     }
 
-    loop {
-        // Fetch batch
-        let batch = database.fetch_batch(last_id, args.batch_size).await?;
+    /// Check the table in every tenant schema, `--tenant-concurrency` schemas at a time
+    async fn check_tenants(&self, source: &TenantSource) -> Result<()> {
+        let args = self.args;
+        let (_, table_name) = args.table_name()?;
+        let tenants =
+            discover_tenants(self.db_url, &table_name, source, &args.exclude_schemas).await?;
+        if tenants.is_empty() {
+            info!("No tenant schemas to process");
+            return Ok(());
+        }
 
-        if batch.is_empty() {
-            break;
+        let overall = self.progress.add(ProgressBar::new(tenants.len() as u64));
+        overall.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "Tenants [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%)",
+                )
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let overall = &overall;
+
+        // In the order discovered, whatever order they finish in
+        let reports: Vec<(String, TableReport)> = stream::iter(tenants)
+            .map(|tenant| async move {
+                let table = args.db_table()?.with_schema(Some(tenant.clone()));
+                let report = self.check_table(table, Some(&tenant)).await?;
+                overall.inc(1);
+                Ok::<_, anyhow::Error>((tenant, report))
+            })
+            .buffered(args.tenant_concurrency)
+            .try_collect()
+            .await?;
+        overall.finish();
+
+        // Summary over all tenants
+        let mut summary = RunSummary::default();
+        let (mut processed, mut broken) = (0, 0);
+        info!("=== Summary of {} tenants ===", reports.len());
+        for (tenant, report) in &reports {
+            if report.broken > 0 {
+                info!(
+                    "  {}: {} broken of {} checked",
+                    tenant, report.broken, report.processed
+                );
+            }
+            processed += report.processed;
+            broken += report.broken;
+            summary.merge(&report.summary);
         }
+        info!("Total records checked: {}", processed);
+        info!("Broken URLs found: {}", broken);
+        info!("Broken rate: {:.2}%", broken_rate(broken, processed));
+        summary.log();
 
-        let batch_len = batch.len() as i64;
+        Ok(())
+    }
 
-        // Update last_id for next iteration
-        if let Some((id, _)) = batch.last() {
-            last_id = *id;
+    /// Check one table in batches (with its own checkpoint), then delete its broken rows
+    /// if requested
+    async fn check_table(&self, table: Table, tenant: Option<&str>) -> Result<TableReport> {
+        let args = self.args;
+        // Log lines of a tenant name it, as tenants can be checked side by side
+        let scope = tenant.map(|t| format!("[{}] ", t)).unwrap_or_default();
+
+        // Connect to database
+        let database = Database::new(self.db_url, table.clone()).await?;
+        // Checkpoints name the table as given, so resuming works however it resolves
+        let checkpoint_table = table.qualified_name();
+
+        // Get total count
+        let total_count = database.get_total_count().await?;
+        info!("{}Total records with URLs: {}", scope, total_count);
+
+        if total_count == 0 {
+            info!("{}No records to process", scope);
+            database.close().await;
+            return Ok(TableReport::default());
         }
 
-        // Check URLs in batch
-        let results = checker.check_batch(batch).await;
+        // Handle resume logic
+        let fresh =
+            || Checkpoint::new(&checkpoint_table, total_count, args.dry_run).for_tenant(tenant);
+        let mut checkpoint = if args.resume && Checkpoint::exists(tenant) {
+            match Checkpoint::load(tenant).await? {
+                Some(cp) if cp.validate(&checkpoint_table, args.dry_run) => {
+                    info!(
+                        "{}Resuming from checkpoint: {}/{} processed, starting after key {}",
+                        scope,
+                        cp.processed,
+                        cp.total_records,
+                        cp.describe_last_key()
+                    );
+                    cp
+                }
+                Some(_) => {
+                    warn!("{}Checkpoint validation failed, starting fresh", scope);
+                    fresh()
+                }
+                None => {
+                    warn!("{}Could not load checkpoint, starting fresh", scope);
+                    fresh()
+                }
+            }
+        } else {
+            if args.resume {
+                info!("{}No checkpoint found, starting fresh", scope);
+            }
+            fresh()
+        };
+
+        // Setup progress bar
+        let pb = self.progress.add(ProgressBar::new(total_count as u64));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{prefix}{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) | Broken: {msg} | ETA: {eta}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        pb.set_prefix(scope.clone());
+        pb.set_position(checkpoint.processed as u64);
+        pb.set_message(format!("{}", checkpoint.broken_count));
 
-        // Process results
-        let broken_results: Vec<&CheckResult> = results.iter().filter(|r| !r.is_valid).collect();
-        let broken_ids: Vec<i64> = broken_results.iter().map(|r| r.id).collect();
+        // CSV output, written batch by batch (tenants checked side by side interleave)
+        let key_columns = database.table().key_columns.clone();
+        self.write_csv(&key_columns, Vec::new())?;
+
+        // Process batches
+        let mut all_broken_keys: Vec<RowKey> = checkpoint.broken_keys.clone();
+
+        let scan = Scan::new(&database, &self.checker, args.batch_size);
+        let mut batches = pin!(scan.batches(checkpoint.last_key.clone()));
+        while let Some(batch) = batches.try_next().await? {
+            let results = &batch.results;
+
+            // Without a working proxy the rest of the run can't be checked; stop before this
+            // batch is recorded so that --resume picks it up again
+            if results.iter().any(|r| r.outcome == Outcome::Unchecked) {
+                pb.abandon();
+                checkpoint.save().await?;
+                return Err(anyhow!(
+                    "All proxies failed; fix the proxy configuration and rerun with --resume"
+                ));
+            }
 
-        // Write broken URLs to CSV if output is specified
-        if let Some(ref mut writer) = csv_writer {
-            for result in &broken_results {
-                writer.write_record([
-                    result.id.to_string(),
-                    result.url.clone(),
-                    result.status_code.map(|s| s.to_string()).unwrap_or_default(),
-                    result.error.clone().unwrap_or_default(),
-                ])?;
+            // Process results (special categories are always reported, whatever their validity)
+            let reported_results: Vec<&CheckResult> = results
+                .iter()
+                .filter(|r| !r.is_valid || r.outcome != Outcome::Valid)
+                .collect();
+            let broken_keys: Vec<RowKey> = reported_results
+                .iter()
+                .filter(|r| should_delete(args, r))
+                .map(|r| batch.key(r).clone())
+                .collect();
+
+            // Write broken URLs to CSV if output is specified
+            if args.output.is_some() {
+                let rows = reported_results.iter().map(|result| {
+                    let key = batch.key(result).values().iter().map(ToString::to_string);
+                    tenant
+                        .map(str::to_string)
+                        .into_iter()
+                        .chain(key)
+                        .chain([
+                            result.display_url(),
+                            result
+                                .status_code
+                                .map(|s| s.to_string())
+                                .unwrap_or_default(),
+                            result.error.clone().unwrap_or_default(),
+                            result.outcome.as_str().to_string(),
+                            result
+                                .timeout
+                                .map(|t| t.as_secs_f64().to_string())
+                                .unwrap_or_default(),
+                            result.describe_annotations(),
+                        ])
+                        .collect::<Vec<String>>()
+                });
+                self.write_csv(&key_columns, rows.collect())?;
             }
-            writer.flush()?;
-        }
 
-        // Collect broken IDs
-        all_broken_ids.extend(&broken_ids);
+            // Write the requests of broken and retried URLs to the HAR file
+            if let Some(har_path) = &args.har {
+                let mut har_writer = self.har_writer.lock().unwrap();
+                let har = match &mut *har_writer {
+                    Some(har) => har,
+                    None => har_writer.insert(HarWriter::create(har_path)?),
+                };
+                for result in results.iter().filter(|r| HarWriter::is_evidence(r)) {
+                    har.write_result(result)?;
+                }
+                har.flush()?;
+            }
 
-        // Update checkpoint
-        checkpoint.update(
-            checkpoint.processed + batch_len,
-            last_id,
-            broken_ids,
-        );
+            // Collect broken keys
+            all_broken_keys.extend(broken_keys.iter().cloned());
 
-        // Save checkpoint periodically (every 10 batches)
-        if checkpoint.current_batch % 10 == 0 {
-            checkpoint.save().await?;
+            // Update checkpoint
+            for result in &reported_results {
+                checkpoint.summary.record(result);
+            }
+            checkpoint.update(
+                checkpoint.processed + results.len() as i64,
+                batch.last_key.clone(),
+                broken_keys,
+            );
+
+            // Save checkpoint periodically (every 10 batches)
+            if checkpoint.current_batch % 10 == 0 {
+                checkpoint.save().await?;
+            }
+
+            // Update progress bar
+            pb.set_position(checkpoint.processed as u64);
+            pb.set_message(format!("{}", checkpoint.broken_count));
         }
 
-        // Update progress bar
-        pb.set_position(checkpoint.processed as u64);
-        pb.set_message(format!("{}", checkpoint.broken_count));
-    }
+        match tenant {
+            // Finished tenants leave room for the running ones
+            Some(_) => pb.finish_and_clear(),
+            None => pb.finish_with_message(format!(
+                "Done! {} broken URLs found",
+                checkpoint.broken_count
+            )),
+        }
 
-    pb.finish_with_message(format!("Done! {} broken URLs found", checkpoint.broken_count));
+        // Save final checkpoint
+        checkpoint.save().await?;
 
-    // Save final checkpoint
-    checkpoint.save().await?;
+        // Summary
+        match tenant {
+            Some(tenant) => info!("=== Summary of tenant {} ===", tenant),
+            None => info!("=== Summary ==="),
+        }
+        info!("Total records checked: {}", checkpoint.processed);
+        info!("Broken URLs found: {}", checkpoint.broken_count);
+        info!(
+            "Broken rate: {:.2}%",
+            broken_rate(checkpoint.broken_count, checkpoint.processed)
+        );
+        checkpoint.summary.log();
+        if checkpoint.summary.count(Outcome::DataUri) > 0 {
+            info!("Inline data URI action: {:?}", args.data_uri_action);
+        }
 
-    // Summary
-    info!("=== Summary ===");
-    info!("Total records checked: {}", checkpoint.processed);
-    info!("Broken URLs found: {}", checkpoint.broken_count);
-    info!(
-        "Broken rate: {:.2}%",
-        (checkpoint.broken_count as f64 / checkpoint.processed as f64) * 100.0
-    );
+        // Delete broken URLs if requested
+        if args.delete && !args.dry_run && !all_broken_keys.is_empty() {
+            let backup = !args.no_backup;
+            if backup {
+                info!(
+                    "{}Backing up and deleting {} broken URL records...",
+                    scope,
+                    all_broken_keys.len()
+                );
+            } else {
+                warn!(
+                    "{}Deleting {} broken URL records WITHOUT backup...",
+                    scope,
+                    all_broken_keys.len()
+                );
+            }
 
-    if let Some(output_path) = &args.output {
-        info!("Broken URLs exported to: {}", output_path);
-    }
+            let deleted = database.delete_by_keys(&all_broken_keys, backup).await?;
+            info!("{}Successfully deleted {} records", scope, deleted);
 
-    // Delete broken URLs if requested
-    if args.delete && !args.dry_run && !all_broken_ids.is_empty() {
-        let backup = !args.no_backup;
-        if backup {
+            if backup {
+                info!(
+                    "{}Backup stored in table: {}",
+                    scope,
+                    database.table().qualified_backup_name()
+                );
+            }
+
+            // Clean up checkpoint after successful deletion
+            Checkpoint::delete(tenant).await?;
+        } else if args.dry_run {
             info!(
-                "Backing up and deleting {} broken URL records...",
-                all_broken_ids.len()
+                "{}DRY RUN - Would delete {} records",
+                scope,
+                all_broken_keys.len()
             );
-        } else {
-            warn!(
-                "Deleting {} broken URL records WITHOUT backup...",
-                all_broken_ids.len()
+        } else if !args.delete {
+            info!(
+                "{}Use --delete flag to remove broken URLs from database",
+                scope
             );
         }
 
-        let deleted = database.delete_by_ids(&all_broken_ids, backup).await?;
-        info!("Successfully deleted {} records", deleted);
+        // Close database connection
+        database.close().await;
 
-        if backup {
-            info!(
-                "Backup stored in table: {}_deleted_backup",
-                args.table
-            );
+        Ok(TableReport {
+            processed: checkpoint.processed,
+            broken: checkpoint.broken_count,
+            summary: checkpoint.summary,
+        })
+    }
+
+    /// Append rows to the CSV output, creating it with its header first
+    fn write_csv(&self, key_columns: &[String], rows: Vec<Vec<String>>) -> Result<()> {
+        let Some(output_path) = &self.args.output else {
+            return Ok(());
+        };
+
+        let mut csv_writer = self.csv_writer.lock().unwrap();
+        let writer = match &mut *csv_writer {
+            Some(writer) => writer,
+            None => {
+                let mut writer = Writer::from_writer(File::create(output_path)?);
+                // One column per key column ("id" by default), after the tenant's schema
+                let schema = self.args.tenant_source().map(|_| "schema");
+                let header = schema
+                    .into_iter()
+                    .chain(key_columns.iter().map(String::as_str))
+                    .chain([
+                        "url",
+                        "status_code",
+                        "error",
+                        "category",
+                        "timeout_secs",
+                        "details",
+                    ]);
+                writer.write_record(header)?;
+                csv_writer.insert(writer)
+            }
+        };
+        for row in rows {
+            writer.write_record(row)?;
         }
+        writer.flush()?;
+        Ok(())
+    }
 
-        // Clean up checkpoint after successful deletion
-        Checkpoint::delete().await?;
-    } else if args.dry_run {
-        info!("DRY RUN - Would delete {} records", all_broken_ids.len());
-    } else if !args.delete {
-        info!("Use --delete flag to remove broken URLs from database");
+    /// Close the outputs and save what the checker recorded
+    fn finish(self) -> Result<()> {
+        if let (Some(_), Some(output_path)) =
+            (self.csv_writer.into_inner().unwrap(), &self.args.output)
+        {
+            info!("Broken URLs exported to: {}", output_path);
+        }
+        if let (Some(har), Some(har_path)) = (self.har_writer.into_inner().unwrap(), &self.args.har)
+        {
+            let entries = har.finish()?;
+            info!("{} requests exported to: {}", entries, har_path);
+        }
+        if let Some(cassette) = self.checker.cassette() {
+            cassette.save()?;
+        }
+        if let Some(chaos) = self.checker.fault_injector() {
+            info!("Injected faults: {}", chaos.describe_counts());
+        }
+        Ok(())
     }
+}
+
+/// Build the URL checker from the command line settings
+fn build_checker(args: &Args) -> Result<UrlChecker> {
+    checker_builder(args)?.build()
+}
+
+/// Checker settings from the command line
+fn checker_builder(args: &Args) -> Result<CheckerBuilder> {
+    // Load per-host request profiles
+    let host_profiles = match &args.host_profiles {
+        Some(path) => {
+            let profiles = HostProfiles::load(path)?;
+            info!("Loaded {} host profiles from {:?}", profiles.len(), path);
+            profiles
+        }
+        None => HostProfiles::default(),
+    };
 
-    // Close database connection
-    database.close().await;
+    // Proxies requests are spread over
+    let proxies = if args.proxies.is_empty() {
+        ProxyPool::direct()
+    } else {
+        let pool = ProxyPool::new(args.proxies.clone(), args.proxy_strategy)?;
+        info!("Using {} proxies ({:?})", pool.len(), args.proxy_strategy);
+        pool
+    };
+
+    // Recorded traffic to capture or stand in for the network
+    let cassette = match (&args.record_cassette, &args.replay_cassette) {
+        (Some(path), _) => Some(Cassette::record(path)),
+        (None, Some(path)) => {
+            info!("Replaying responses from cassette {:?}", path);
+            Some(Cassette::replay(path)?)
+        }
+        (None, None) => None,
+    };
 
-    Ok(())
+    // Faults injected for chaos testing
+    let fault_rates = args.fault_rates();
+    let chaos = fault_rates.is_enabled().then(|| {
+        warn!(
+            "CHAOS MODE - {:.1}% of requests are replaced by injected faults",
+            fault_rates.total() * 100.0
+        );
+        if args.delete && !args.dry_run {
+            warn!("Rows failing only because of injected faults can be deleted; run against a copy of the database");
+        }
+        Arc::new(FaultInjector::new(fault_rates, args.chaos_seed))
+    });
+
+    // Validation stages, in order
+    let pipeline = Pipeline::from_kinds(&args.validators, &args.validator_settings())?;
+    info!("Validation pipeline: {:?}", pipeline);
+
+    // User rules overriding the classification of responses
+    let script = match &args.classify_script {
+        Some(path) => {
+            let script = ClassifyScript::load(path, Duration::from_millis(args.script_timeout_ms))?;
+            info!("Classification script: {:?}", path);
+            Some(Arc::new(script))
+        }
+        None => None,
+    };
+
+    // Retry of 403 responses with the supplier's Referer
+    let hotlink = (!args.no_hotlink_retry).then(|| {
+        let mut hotlink = HotlinkRetry::default();
+        hotlink.referer = args.referer.clone();
+        hotlink.send_origin = args.send_origin;
+        hotlink
+    });
+
+    // Create URL checker with retry configuration
+    Ok(UrlChecker::builder()
+        .concurrency(args.concurrency)
+        .timeout(Duration::from_secs(args.timeout))
+        .connect_timeout(Duration::from_secs(args.connect_timeout))
+        .retry_attempts(args.retry_attempts)
+        .retry_delay(Duration::from_secs(args.retry_delay))
+        .timeout_multiplier(args.retry_timeout_multiplier)
+        .profiles(host_profiles)
+        .proxies(proxies)
+        .tls_insecure_hosts(&args.tls_insecure_hosts)
+        .network_policy(NetworkPolicy::new(
+            args.network_policy,
+            &args.allow_cidr,
+            &args.deny_cidr,
+        )?)
+        .hotlink_retry(hotlink)
+        .record_exchanges(args.har.is_some())
+        .cassette(cassette.map(Arc::new))
+        .fault_injection(chaos)
+        .pipeline(pipeline)
+        .script(script))
+}
+
+/// Percentage of checked rows found broken (0 when nothing was checked)
+fn broken_rate(broken: i64, processed: i64) -> f64 {
+    if processed == 0 {
+        return 0.0;
+    }
+    broken as f64 / processed as f64 * 100.0
+}
+
+/// Whether a reported result is deleted by a `--delete` run
+fn should_delete(args: &Args, result: &CheckResult) -> bool {
+    match result.outcome {
+        Outcome::Broken | Outcome::DomainNotFound => true,
+        Outcome::Valid | Outcome::Unchecked => false,
+        Outcome::DataUri => args.data_uri_action.should_delete(result.is_valid),
+        // Left for a separate re-signing process
+        Outcome::ExpiredSignature => false,
+        Outcome::HotlinkProtected => false,
+        // The image's real state is hidden behind a bot challenge
+        Outcome::Blocked => false,
+        // Internal addresses are never requested, so the image was not checked
+        Outcome::BlockedByPolicy => false,
+        // Certificate problems are chased with the supplier instead
+        Outcome::TlsError => false,
+        // Outcomes added later are kept until deleting them is a deliberate choice
+        _ => false,
+    }
 }
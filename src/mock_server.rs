@@ -0,0 +1,559 @@
+use crate::checker::{CheckResult, CheckerBuilder, Outcome, UrlChecker};
+use crate::policy::{NetworkPolicy, PolicyMode};
+use anyhow::Result;
+use base64::Engine;
+use clap::ValueEnum;
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How long the slow scenario waits before answering (longer than the first attempt's timeout)
+const SLOW_RESPONSE_DELAY: Duration = Duration::from_millis(1500);
+
+/// A scripted server behaviour and the classification it must get
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub name: &'static str,
+    pub path: &'static str,
+    pub description: &'static str,
+    pub outcome: Outcome,
+    pub status: Option<u16>,
+}
+
+impl Scenario {
+    /// Compare a result with the expected classification
+    pub fn verify(&self, result: &CheckResult) -> Result<(), String> {
+        if result.outcome == self.outcome && result.status_code == self.status {
+            return Ok(());
+        }
+        Err(format!(
+            "expected {} ({}), got {} ({})",
+            self.outcome.as_str(),
+            describe_status(self.status),
+            result.outcome.as_str(),
+            describe_status(result.status_code)
+        ))
+    }
+}
+
+fn describe_status(status: Option<u16>) -> String {
+    status
+        .map(|status| format!("HTTP {}", status))
+        .unwrap_or_else(|| "no response".to_string())
+}
+
+/// Scenarios the mock server can play
+pub fn scenarios() -> Vec<Scenario> {
+    let scenario = |name, path, description, outcome, status| Scenario {
+        name,
+        path,
+        description,
+        outcome,
+        status,
+    };
+    vec![
+        scenario(
+            "image",
+            "/image.png",
+            "200 with a PNG image",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "not-found",
+            "/missing.jpg",
+            "404",
+            Outcome::Broken,
+            Some(404),
+        ),
+        scenario(
+            "head-not-allowed",
+            "/head-405.png",
+            "405 on HEAD, image on GET",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "rate-limited",
+            "/rate-limited.png",
+            "429 with Retry-After once, then the image",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "unavailable",
+            "/unavailable.png",
+            "503 on every attempt",
+            Outcome::Broken,
+            Some(503),
+        ),
+        scenario(
+            "slow",
+            "/slow.png",
+            "answers after the first attempt's timeout",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "redirect",
+            "/redirect.png",
+            "302 to the image",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "redirect-to-404",
+            "/redirect-missing.png",
+            "301 to a missing image",
+            Outcome::Broken,
+            Some(404),
+        ),
+        scenario(
+            "redirect-loop",
+            "/loop.png",
+            "redirects to itself",
+            Outcome::Broken,
+            None,
+        ),
+        scenario(
+            "soft-404",
+            "/soft-404.png",
+            "200 with an HTML error page (not detectable from the status)",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "truncated",
+            "/truncated.png",
+            "200 whose body ends before Content-Length",
+            Outcome::Valid,
+            Some(200),
+        ),
+        scenario(
+            "hotlink",
+            "/hotlink.png",
+            "403 unless a Referer is sent",
+            Outcome::HotlinkProtected,
+            Some(200),
+        ),
+        scenario(
+            "challenge",
+            "/challenge.png",
+            "Cloudflare challenge page",
+            Outcome::Blocked,
+            Some(403),
+        ),
+        scenario(
+            "block-page",
+            "/block-page.png",
+            "Cloudflare block page, recognised from its body",
+            Outcome::Blocked,
+            Some(403),
+        ),
+        scenario(
+            "origin-outage",
+            "/origin-outage.png",
+            "503 HTML error page of the origin through Cloudflare",
+            Outcome::Broken,
+            Some(503),
+        ),
+    ]
+}
+
+/// How the response times of synthetic load are spread around their mean
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LatencyDistribution {
+    /// Every response takes the mean latency
+    Fixed,
+    /// Uniform between zero and twice the mean
+    Uniform,
+    /// Exponential with the given mean (many fast responses, a long tail)
+    Exponential,
+}
+
+/// Synthetic responses served under `/bench/` for benchmarks
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticLoad {
+    pub latency: LatencyDistribution,
+    pub mean_latency: Duration,
+    /// Share of requests answered with 404
+    pub error_rate: f64,
+    /// Share of requests answered with 503 (retried by the checker)
+    pub server_error_rate: f64,
+}
+
+impl SyntheticLoad {
+    /// Draw the latency and status of one response
+    fn sample(&self) -> (Duration, &'static str) {
+        let mut rng = rand::rng();
+        let latency = match self.latency {
+            LatencyDistribution::Fixed => self.mean_latency,
+            LatencyDistribution::Uniform => self.mean_latency.mul_f64(rng.random_range(0.0..2.0)),
+            LatencyDistribution::Exponential => {
+                let u: f64 = rng.random();
+                self.mean_latency.mul_f64(-(1.0 - u).ln())
+            }
+        };
+
+        let roll: f64 = rng.random();
+        let status = if roll < self.error_rate {
+            "404 Not Found"
+        } else if roll < self.error_rate + self.server_error_rate {
+            "503 Service Unavailable"
+        } else {
+            "200 OK"
+        };
+        (latency, status)
+    }
+}
+
+/// State shared by the server's connections
+struct ServerState {
+    /// Requests seen per scenario path, for scenarios that change over attempts
+    hits: Mutex<HashMap<String, u32>>,
+    load: Option<SyntheticLoad>,
+}
+
+/// In-process HTTP server on localhost playing the scripted scenarios
+///
+/// Scenario responses close their connection, so each request is answered afresh.
+/// Synthetic load keeps connections alive, like a CDN would.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+}
+
+impl MockServer {
+    /// Bind to a free localhost port and start serving the scenarios
+    pub async fn start() -> Result<Self> {
+        Self::serve(None).await
+    }
+
+    /// Like `start`, also serving synthetic load under `/bench/`
+    pub async fn start_with_load(load: SyntheticLoad) -> Result<Self> {
+        Self::serve(Some(load)).await
+    }
+
+    async fn serve(load: Option<SyntheticLoad>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(ServerState {
+            hits: Mutex::new(HashMap::new()),
+            load,
+        });
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, server_state.clone()));
+            }
+        });
+
+        Ok(Self { addr, state })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Address requests go to, for allowing it in the network policy
+    pub fn ip(&self) -> String {
+        self.addr.ip().to_string()
+    }
+
+    /// Number of requests a scenario path received
+    pub fn hits(&self, path: &str) -> u32 {
+        self.state
+            .hits
+            .lock()
+            .unwrap()
+            .get(path)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Checker settings the scenarios are written for: 1s timeout doubling per retry,
+    /// two retries without delay, and localhost allowed by the network policy
+    pub fn checker_builder(&self) -> Result<CheckerBuilder> {
+        let policy = NetworkPolicy::new(PolicyMode::BlockInternal, &[self.ip()], &[])?;
+        Ok(UrlChecker::builder()
+            .concurrency(10)
+            .timeout(Duration::from_secs(1))
+            .retry_attempts(2)
+            .retry_delay(Duration::ZERO)
+            .network_policy(policy))
+    }
+
+    /// Checker with the settings of [`MockServer::checker_builder`]
+    pub fn checker(&self) -> Result<UrlChecker> {
+        self.checker_builder()?.build()
+    }
+
+    /// Check every scenario in one batch, returning each with its result
+    pub async fn run_scenarios(&self) -> Result<Vec<(Scenario, CheckResult)>> {
+        let scenarios = scenarios();
+        let urls = scenarios
+            .iter()
+            .enumerate()
+            .map(|(id, scenario)| (id as i64, self.url(scenario.path)))
+            .collect();
+
+        let mut results = self.checker()?.check_batch(urls).await;
+        results.sort_by_key(|result| result.id);
+        Ok(scenarios.into_iter().zip(results).collect())
+    }
+}
+
+/// Parsed request line and the headers scenarios look at
+struct Request {
+    method: String,
+    path: String,
+    referer: Option<String>,
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<ServerState>) {
+    while let Some(request) = read_request(&mut stream).await {
+        let head = request.method == "HEAD";
+        let (response, keep_alive) = match state.load {
+            Some(load) if request.path.starts_with("/bench/") => {
+                let (latency, status) = load.sample();
+                tokio::time::sleep(latency).await;
+                (synthetic_reply(status, head), true)
+            }
+            _ => {
+                let hit = {
+                    let mut hits = state.hits.lock().unwrap();
+                    let count = hits.entry(request.path.clone()).or_insert(0);
+                    *count += 1;
+                    *count
+                };
+                (scenario_reply(&request, hit).await, false)
+            }
+        };
+
+        if stream.write_all(&response).await.is_err() || !keep_alive {
+            break;
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Response of the scripted scenario at the request's path
+async fn scenario_reply(request: &Request, hit: u32) -> Vec<u8> {
+    let head = request.method == "HEAD";
+    match request.path.as_str() {
+        "/image.png" | "/redirect-target.png" => image(head),
+        "/missing.jpg" => reply("404 Not Found", &[], b"", head),
+        "/head-405.png" if head => reply("405 Method Not Allowed", &[("Allow", "GET")], b"", head),
+        "/head-405.png" => image(head),
+        "/rate-limited.png" if hit == 1 => {
+            reply("429 Too Many Requests", &[("Retry-After", "1")], b"", head)
+        }
+        "/rate-limited.png" => image(head),
+        "/unavailable.png" => reply("503 Service Unavailable", &[], b"", head),
+        "/slow.png" => {
+            tokio::time::sleep(SLOW_RESPONSE_DELAY).await;
+            image(head)
+        }
+        "/redirect.png" => reply(
+            "302 Found",
+            &[("Location", "/redirect-target.png")],
+            b"",
+            head,
+        ),
+        "/redirect-missing.png" => reply(
+            "301 Moved Permanently",
+            &[("Location", "/gone.jpg")],
+            b"",
+            head,
+        ),
+        "/loop.png" => reply("302 Found", &[("Location", "/loop.png")], b"", head),
+        "/soft-404.png" => reply(
+            "200 OK",
+            &[("Content-Type", "text/html")],
+            b"<html><body>Image not found</body></html>",
+            head,
+        ),
+        "/truncated.png" => {
+            // Announce more than is sent, then close the connection
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                tiny_png().len() * 10
+            )
+            .into_bytes();
+            if !head {
+                response.extend_from_slice(&tiny_png()[..16]);
+            }
+            response
+        }
+        "/hotlink.png" if request.referer.is_some() => image(head),
+        "/hotlink.png" => reply("403 Forbidden", &[], b"", head),
+        "/challenge.png" => reply(
+            "403 Forbidden",
+            &[
+                ("Content-Type", "text/html"),
+                ("Server", "cloudflare"),
+                ("cf-mitigated", "challenge"),
+            ],
+            b"<html>Just a moment...</html>",
+            head,
+        ),
+        "/block-page.png" => reply(
+            "403 Forbidden",
+            &[("Content-Type", "text/html"), ("Server", "cloudflare")],
+            b"<html><title>Attention Required! | Cloudflare</title></html>",
+            head,
+        ),
+        "/origin-outage.png" => reply(
+            "503 Service Unavailable",
+            &[
+                ("Content-Type", "text/html"),
+                ("Server", "cloudflare"),
+                ("cf-ray", "8a1b2c3d4e5f6789-AMS"),
+            ],
+            b"<html><h1>Down for maintenance</h1></html>",
+            head,
+        ),
+        _ => reply("404 Not Found", &[], b"", head),
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 || buf.len() > 16 * 1024 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines = text.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let referer = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("referer"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Some(Request {
+        method,
+        path,
+        referer,
+    })
+}
+
+/// Raw HTTP/1.1 response; HEAD responses keep the headers but drop the body
+fn reply(status: &str, headers: &[(&str, &str)], body: &[u8], head: bool) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+
+    let mut response = response.into_bytes();
+    if !head {
+        response.extend_from_slice(body);
+    }
+    response
+}
+
+/// Synthetic load response, keeping the connection open
+fn synthetic_reply(status: &str, head: bool) -> Vec<u8> {
+    let body = if status.starts_with("200") {
+        tiny_png()
+    } else {
+        Vec::new()
+    };
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        status,
+        body.len()
+    )
+    .into_bytes();
+    if !head {
+        response.extend_from_slice(&body);
+    }
+    response
+}
+
+fn image(head: bool) -> Vec<u8> {
+    reply(
+        "200 OK",
+        &[("Content-Type", "image/png")],
+        &tiny_png(),
+        head,
+    )
+}
+
+/// A 1x1 PNG
+fn tiny_png() -> Vec<u8> {
+    base64::engine::general_purpose::STANDARD
+        .decode("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==")
+        .expect("valid base64")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scenarios_are_classified() {
+        let server = MockServer::start().await.unwrap();
+        for (scenario, result) in server.run_scenarios().await.unwrap() {
+            if let Err(mismatch) = scenario.verify(&result) {
+                panic!("{}: {} ({:?})", scenario.name, mismatch, result.error);
+            }
+        }
+
+        // The retry and fallback paths were actually taken
+        assert_eq!(server.hits("/rate-limited.png"), 2);
+        assert_eq!(server.hits("/unavailable.png"), 3);
+        assert_eq!(server.hits("/slow.png"), 2);
+        assert_eq!(server.hits("/head-405.png"), 2);
+        assert_eq!(server.hits("/hotlink.png"), 2);
+        // HEAD, then GET for the page, on every attempt of the retried outage
+        assert_eq!(server.hits("/block-page.png"), 2);
+        assert_eq!(server.hits("/origin-outage.png"), 6);
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_load() {
+        let load = |error_rate| SyntheticLoad {
+            latency: LatencyDistribution::Uniform,
+            mean_latency: Duration::from_millis(5),
+            error_rate,
+            server_error_rate: 0.0,
+        };
+
+        let server = MockServer::start_with_load(load(0.0)).await.unwrap();
+        let urls = (0..20)
+            .map(|id| (id, server.url(&format!("/bench/{}.png", id))))
+            .collect();
+        let results = server.checker().unwrap().check_batch(urls).await;
+        assert!(results
+            .iter()
+            .all(|result| result.outcome == Outcome::Valid));
+        // Synthetic requests are not counted as scenario hits
+        assert_eq!(server.hits("/bench/0.png"), 0);
+
+        let server = MockServer::start_with_load(load(1.0)).await.unwrap();
+        let results = server
+            .checker()
+            .unwrap()
+            .check_batch(vec![(1, server.url("/bench/1.png"))])
+            .await;
+        assert_eq!(results[0].status_code, Some(404));
+    }
+}
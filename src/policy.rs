@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use ipnet::IpNet;
+use reqwest::Url;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Cloud metadata endpoints (AWS/GCP/Azure, AWS IPv6, Alibaba Cloud)
+const METADATA_ADDRESSES: [IpAddr; 3] = [
+    IpAddr::V4(Ipv4Addr::new(169, 254, 169, 254)),
+    IpAddr::V6(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254)),
+    IpAddr::V4(Ipv4Addr::new(100, 100, 100, 200)),
+];
+
+/// Which destination addresses requests may go to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PolicyMode {
+    /// Block loopback, private, link-local and metadata addresses
+    BlockInternal,
+    /// Allow every address (only `--deny-cidr` ranges are blocked)
+    AllowAll,
+}
+
+/// Network policy applied to every resolved address, including redirect targets
+///
+/// URLs come from third-party supplier feeds, so a row may point at `localhost`,
+/// a cloud metadata endpoint or an internal service.
+#[derive(Debug, Clone)]
+pub struct NetworkPolicy {
+    mode: PolicyMode,
+    /// Ranges allowed even though they are internal
+    allow: Vec<IpNet>,
+    /// Ranges always blocked
+    deny: Vec<IpNet>,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self {
+            mode: PolicyMode::BlockInternal,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl NetworkPolicy {
+    /// Create a policy from a mode and CIDR exceptions (e.g. `10.20.0.0/16`)
+    pub fn new(mode: PolicyMode, allow: &[String], deny: &[String]) -> Result<Self> {
+        Ok(Self {
+            mode,
+            allow: parse_cidrs(allow)?,
+            deny: parse_cidrs(deny)?,
+        })
+    }
+
+    /// Check a destination address, returning why it is blocked
+    pub fn check(&self, ip: IpAddr) -> Option<BlockedAddress> {
+        let ip = canonical(ip);
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return Some(BlockedAddress::new(ip, "denied"));
+        }
+        if self.mode == PolicyMode::AllowAll || self.allow.iter().any(|net| net.contains(&ip)) {
+            return None;
+        }
+
+        internal_range(ip).map(|range| BlockedAddress::new(ip, range))
+    }
+
+    /// Check a URL whose host is an IP address (domains are checked after resolution)
+    pub fn check_url(&self, url: &Url) -> Option<BlockedAddress> {
+        let host = url
+            .host_str()?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        self.check(host.parse().ok()?)
+    }
+}
+
+/// Kinds of ranges an address can be blocked for
+const RANGES: [&str; 7] = [
+    "denied",
+    "metadata",
+    "loopback",
+    "private",
+    "link-local",
+    "shared (CGNAT)",
+    "unspecified",
+];
+
+/// A destination address rejected by the network policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BlockedAddress {
+    pub ip: IpAddr,
+    /// Kind of range the address belongs to (e.g. "loopback", "metadata")
+    pub range: &'static str,
+}
+
+impl BlockedAddress {
+    fn new(ip: IpAddr, range: &'static str) -> Self {
+        Self { ip, range }
+    }
+
+    /// Find a policy rejection in the source chain of a request error
+    pub fn find<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a BlockedAddress> {
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if let Some(blocked) = error.downcast_ref::<BlockedAddress>() {
+                return Some(blocked);
+            }
+            source = error.source();
+        }
+        None
+    }
+}
+
+impl fmt::Display for BlockedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Blocked by network policy: {} is a {} address",
+            self.ip, self.range
+        )
+    }
+}
+
+impl Error for BlockedAddress {}
+
+/// `BlockedAddress` as read from a file, before its range name is checked
+#[derive(Deserialize)]
+struct RawBlockedAddress {
+    ip: IpAddr,
+    range: String,
+}
+
+impl<'de> Deserialize<'de> for BlockedAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawBlockedAddress::deserialize(deserializer)?;
+        RANGES
+            .iter()
+            .find(|range| **range == raw.range)
+            .map(|range| BlockedAddress::new(raw.ip, range))
+            .ok_or_else(|| D::Error::custom(format!("unknown address range '{}'", raw.range)))
+    }
+}
+
+fn parse_cidrs(values: &[String]) -> Result<Vec<IpNet>> {
+    values
+        .iter()
+        .map(|value| {
+            // A bare address is a single-host range
+            value
+                .parse::<IpNet>()
+                .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| anyhow!("Invalid CIDR range '{}'", value))
+        })
+        .collect()
+}
+
+/// Treat IPv4-mapped IPv6 addresses (`::ffff:127.0.0.1`) as the IPv4 address
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+/// Name of the internal range an address belongs to
+fn internal_range(ip: IpAddr) -> Option<&'static str> {
+    if METADATA_ADDRESSES.contains(&ip) {
+        return Some("metadata");
+    }
+
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            if v4.is_loopback() {
+                Some("loopback")
+            } else if v4.is_private() {
+                Some("private")
+            } else if v4.is_link_local() {
+                Some("link-local")
+            } else if a == 100 && (64..128).contains(&b) {
+                Some("shared (CGNAT)")
+            } else if v4.is_unspecified() || v4.is_broadcast() || a == 0 {
+                Some("unspecified")
+            } else {
+                None
+            }
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            if v6.is_loopback() {
+                Some("loopback")
+            } else if v6.is_unspecified() {
+                Some("unspecified")
+            } else if first & 0xfe00 == 0xfc00 {
+                Some("private")
+            } else if first & 0xffc0 == 0xfe80 {
+                Some("link-local")
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocked(policy: &NetworkPolicy, ip: &str) -> Option<&'static str> {
+        policy.check(ip.parse().unwrap()).map(|b| b.range)
+    }
+
+    #[test]
+    fn test_block_internal() {
+        let policy = NetworkPolicy::default();
+        assert_eq!(blocked(&policy, "127.0.0.1"), Some("loopback"));
+        assert_eq!(blocked(&policy, "::1"), Some("loopback"));
+        assert_eq!(blocked(&policy, "10.1.2.3"), Some("private"));
+        assert_eq!(blocked(&policy, "192.168.0.10"), Some("private"));
+        assert_eq!(blocked(&policy, "fd12::1"), Some("private"));
+        assert_eq!(blocked(&policy, "169.254.169.254"), Some("metadata"));
+        assert_eq!(blocked(&policy, "169.254.1.1"), Some("link-local"));
+        assert_eq!(blocked(&policy, "::ffff:127.0.0.1"), Some("loopback"));
+        assert_eq!(blocked(&policy, "0.0.0.0"), Some("unspecified"));
+        assert_eq!(blocked(&policy, "93.184.216.34"), None);
+        assert_eq!(blocked(&policy, "2606:2800:220:1::1"), None);
+    }
+
+    #[test]
+    fn test_allow_and_deny_ranges() {
+        let policy = NetworkPolicy::new(
+            PolicyMode::BlockInternal,
+            &["10.20.0.0/16".to_string()],
+            &["93.184.216.34".to_string()],
+        )
+        .unwrap();
+        assert_eq!(blocked(&policy, "10.20.5.5"), None);
+        assert_eq!(blocked(&policy, "10.21.5.5"), Some("private"));
+        assert_eq!(blocked(&policy, "93.184.216.34"), Some("denied"));
+
+        let allow_all = NetworkPolicy::new(PolicyMode::AllowAll, &[], &[]).unwrap();
+        assert_eq!(blocked(&allow_all, "127.0.0.1"), None);
+        assert!(
+            NetworkPolicy::new(PolicyMode::AllowAll, &["10.0.0.0/33".to_string()], &[]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_blocked_address_round_trip() {
+        let policy = NetworkPolicy::default();
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "fe80::1",
+        ] {
+            let blocked = policy.check(ip.parse().unwrap()).unwrap();
+            let json = serde_json::to_string(&blocked).unwrap();
+            assert_eq!(
+                serde_json::from_str::<BlockedAddress>(&json).unwrap(),
+                blocked
+            );
+        }
+        assert!(serde_json::from_str::<BlockedAddress>(
+            r#"{"ip": "127.0.0.1", "range": "public"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_check_url() {
+        let policy = NetworkPolicy::default();
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert!(policy
+            .check_url(&url("http://169.254.169.254/latest/meta-data"))
+            .is_some());
+        assert!(policy.check_url(&url("http://[::1]:8080/a.jpg")).is_some());
+        assert!(policy
+            .check_url(&url("https://img.example.com/a.jpg"))
+            .is_none());
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{anyhow, Context, Result};
+use regex::{Regex, RegexBuilder};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE, USER_AGENT};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Request settings for image hosts matching a pattern
+///
+/// Unset fields fall back to the global command line settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostProfile {
+    /// Host glob, e.g. `*.cdn.example.com` (`*` matches any characters)
+    #[serde(default)]
+    pub host: Option<String>,
+
+    /// Host regular expression, alternative to `host`
+    #[serde(default)]
+    pub host_regex: Option<String>,
+
+    /// User-Agent to send instead of the default one
+    #[serde(default)]
+    pub user_agent: Option<String>,
+
+    /// Extra request headers (e.g. `Authorization`, `Accept`, API keys)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Cookies sent as a single `Cookie` header
+    #[serde(default)]
+    pub cookies: BTreeMap<String, String>,
+
+    /// TCP connect timeout in seconds
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Total request timeout in seconds
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// Maximum concurrent requests to matching hosts (within the global limit)
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+
+    /// Number of retry attempts for retryable failures
+    #[serde(default)]
+    pub retry_attempts: Option<u32>,
+
+    /// Delay in seconds before the final retry
+    #[serde(default)]
+    pub retry_delay_secs: Option<u64>,
+}
+
+impl HostProfile {
+    /// Name used in logs
+    pub fn pattern(&self) -> &str {
+        self.host
+            .as_deref()
+            .or(self.host_regex.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Default headers for requests using this profile
+    ///
+    /// Values may reference environment variables as `${NAME}`, so that tokens
+    /// don't have to be stored in the profile file.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", name))?;
+            // The route's user agent would silently replace it
+            if name == USER_AGENT {
+                return Err(anyhow!(
+                    "Set the User-Agent with 'user_agent' rather than in 'headers'"
+                ));
+            }
+            let value = HeaderValue::from_str(&expand_env(value)?)
+                .with_context(|| format!("Invalid value for header '{}'", name))?;
+            map.insert(name, value);
+        }
+
+        if !self.cookies.is_empty() {
+            let mut pairs = Vec::with_capacity(self.cookies.len());
+            for (name, value) in &self.cookies {
+                pairs.push(format!("{}={}", name, expand_env(value)?));
+            }
+            let value = HeaderValue::from_str(&pairs.join("; ")).context("Invalid cookie value")?;
+            map.insert(COOKIE, value);
+        }
+
+        Ok(map)
+    }
+}
+
+/// On-disk format of the host profile file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    profiles: Vec<HostProfile>,
+}
+
+/// Ordered host profiles; the first profile whose pattern matches a host wins
+#[derive(Debug, Default)]
+pub struct HostProfiles {
+    entries: Vec<(Regex, HostProfile)>,
+}
+
+impl HostProfiles {
+    /// Load profiles from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read host profiles at {:?}: {}", path, e))?;
+        let file: ProfileFile = serde_json::from_str(&data)
+            .map_err(|e| anyhow!("Invalid host profiles in {:?}: {}", path, e))?;
+        Self::new(file.profiles)
+    }
+
+    /// Compile the host patterns of a list of profiles
+    pub fn new(profiles: Vec<HostProfile>) -> Result<Self> {
+        let mut entries = Vec::with_capacity(profiles.len());
+
+        for profile in profiles {
+            let pattern = match (&profile.host, &profile.host_regex) {
+                (Some(glob), None) => glob_to_regex(glob),
+                (None, Some(regex)) => regex.clone(),
+                _ => {
+                    return Err(anyhow!(
+                        "Each host profile needs exactly one of 'host' or 'host_regex'"
+                    ))
+                }
+            };
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| anyhow!("Invalid host pattern '{}': {}", profile.pattern(), e))?;
+
+            // Fail at startup rather than on the first matching request
+            profile
+                .header_map()
+                .with_context(|| format!("Host profile '{}'", profile.pattern()))?;
+            if profile.concurrency == Some(0)
+                || profile.timeout_secs == Some(0)
+                || profile.connect_timeout_secs == Some(0)
+            {
+                return Err(anyhow!(
+                    "Host profile '{}': concurrency and timeouts must be greater than 0",
+                    profile.pattern()
+                ));
+            }
+
+            entries.push((regex, profile));
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// All profiles, in matching order
+    pub fn profiles(&self) -> impl Iterator<Item = &HostProfile> {
+        self.entries.iter().map(|(_, profile)| profile)
+    }
+
+    /// Index of the first profile matching a host
+    pub fn find(&self, host: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(regex, _)| regex.is_match(host))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Compile a host glob (e.g. `*.cdn.example.com`) into a case-insensitive regex
+pub fn glob_regex(glob: &str) -> Result<Regex> {
+    RegexBuilder::new(&glob_to_regex(glob))
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| anyhow!("Invalid host pattern '{}': {}", glob, e))
+}
+
+/// Translate a host glob into an anchored regular expression
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for (i, part) in glob.split('*').enumerate() {
+        if i > 0 {
+            pattern.push_str(".*");
+        }
+        pattern.push_str(&regex::escape(part));
+    }
+    pattern.push('$');
+    pattern
+}
+
+/// Replace `${NAME}` references with environment variable values
+fn expand_env(value: &str) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated '${{' in '{}'", value))?;
+        let name = &rest[start + 2..start + end];
+        let var =
+            std::env::var(name).map_err(|_| anyhow!("Environment variable {} is not set", name))?;
+
+        result.push_str(&rest[..start]);
+        result.push_str(&var);
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(host: &str) -> HostProfile {
+        HostProfile {
+            host: Some(host.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_first_match() {
+        let profiles = HostProfiles::new(vec![
+            profile("img.partner.com"),
+            profile("*.partner.com"),
+            HostProfile {
+                host_regex: Some(r"^cdn\d+\.example\.org$".to_string()),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(profiles.find("img.partner.com"), Some(0));
+        assert_eq!(profiles.find("static.PARTNER.com"), Some(1));
+        assert_eq!(profiles.find("partner.com.evil.net"), None);
+        assert_eq!(profiles.find("cdn12.example.org"), Some(2));
+        assert_eq!(profiles.find("cdn.example.org"), None);
+    }
+
+    #[test]
+    fn test_header_map() {
+        std::env::set_var("PROFILES_TEST_TOKEN", "s3cret");
+        let profile = HostProfile {
+            headers: BTreeMap::from([
+                (
+                    "X-Api-Key".to_string(),
+                    "${PROFILES_TEST_TOKEN}".to_string(),
+                ),
+                ("Accept".to_string(), "image/*".to_string()),
+            ]),
+            cookies: BTreeMap::from([
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+            ]),
+            ..profile("*")
+        };
+
+        let map = profile.header_map().unwrap();
+        assert_eq!(map["x-api-key"], "s3cret");
+        assert_eq!(map["accept"], "image/*");
+        assert_eq!(map["cookie"], "a=1; b=2");
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        // Neither or both patterns
+        assert!(HostProfiles::new(vec![HostProfile::default()]).is_err());
+        assert!(HostProfiles::new(vec![HostProfile {
+            host_regex: Some("x".to_string()),
+            ..profile("x")
+        }])
+        .is_err());
+
+        // Missing environment variable
+        assert!(HostProfiles::new(vec![HostProfile {
+            headers: BTreeMap::from([(
+                "Authorization".to_string(),
+                "Bearer ${PROFILES_TEST_MISSING}".to_string()
+            )]),
+            ..profile("x")
+        }])
+        .is_err());
+
+        // User agents have their own setting
+        assert!(HostProfiles::new(vec![HostProfile {
+            headers: BTreeMap::from([("user-agent".to_string(), "Bot/1.0".to_string())]),
+            ..profile("x")
+        }])
+        .is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use reqwest::{Proxy, Url};
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::warn;
+
+/// Consecutive failed probes after which a proxy is taken out of rotation
+const MAX_PROXY_FAILURES: u32 = 3;
+
+/// Timeout for probing whether a proxy itself is reachable
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Refusals that mean the proxy won't serve us at all: rejected credentials and
+/// failed handshakes
+const PROXY_REFUSALS: [&str; 10] = [
+    "tunnel error: proxy authorization required",
+    "tunnel error: proxy response headers too long",
+    "tunnel error: unexpected end of file",
+    "tunnel error: io error establishing tunnel",
+    "SOCKS error: credentials not accepted",
+    "SOCKS error: server does not support user/pass authentication",
+    "SOCKS error: server implements authentication incorrectly",
+    "SOCKS error: server ident service failed",
+    "SOCKS error: server ident service did not recognise client identifier",
+    "SOCKS error: io error during SOCKS handshake",
+];
+
+/// SOCKS failures that aren't the proxy refusing the request: connecting to the proxy
+/// itself, and the proxy reporting the destination unreachable
+const SOCKS_NON_REFUSALS: [&str; 5] = [
+    "failed to create underlying connection",
+    "network unreachable",
+    "host unreachable",
+    "connection refused",
+    "ttl expired",
+];
+
+/// How requests are spread over the proxies of a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProxyStrategy {
+    /// Rotate through healthy proxies request by request
+    RoundRobin,
+    /// Always use the same proxy for a given host (while it is healthy)
+    PerHost,
+}
+
+/// A proxy and its health
+#[derive(Debug)]
+struct ProxyState {
+    url: String,
+    failures: AtomicU32,
+    healthy: AtomicBool,
+}
+
+/// Pool of HTTP(S)/SOCKS5 proxies with health tracking
+#[derive(Debug)]
+pub struct ProxyPool {
+    proxies: Vec<ProxyState>,
+    strategy: ProxyStrategy,
+    next: AtomicUsize,
+}
+
+impl ProxyPool {
+    /// Create a pool from proxy URLs (`http://`, `https://`, `socks5://` or `socks5h://`)
+    pub fn new(urls: Vec<String>, strategy: ProxyStrategy) -> Result<Self> {
+        let mut proxies = Vec::with_capacity(urls.len());
+
+        for url in urls {
+            let parsed =
+                Url::parse(&url).map_err(|e| anyhow!("Invalid proxy URL '{}': {}", url, e))?;
+            if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                return Err(anyhow!(
+                    "Unsupported proxy scheme '{}' in '{}' (expected http, https, socks5 or socks5h)",
+                    parsed.scheme(),
+                    url
+                ));
+            }
+            Proxy::all(&url).map_err(|e| anyhow!("Invalid proxy URL '{}': {}", url, e))?;
+
+            proxies.push(ProxyState {
+                url,
+                failures: AtomicU32::new(0),
+                healthy: AtomicBool::new(true),
+            });
+        }
+
+        Ok(Self {
+            proxies,
+            strategy,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Pool that sends requests directly
+    pub fn direct() -> Self {
+        Self {
+            proxies: Vec::new(),
+            strategy: ProxyStrategy::RoundRobin,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proxies.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.proxies.len()
+    }
+
+    /// Proxy URLs, in index order
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.proxies.iter().map(|p| p.url.as_str())
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        &self.proxies[index].url
+    }
+
+    /// Number of proxies still in rotation
+    pub fn healthy_count(&self) -> usize {
+        self.proxies
+            .iter()
+            .filter(|p| p.healthy.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// Pick a healthy proxy for a request to `host`, or `None` if none is left
+    pub fn select(&self, host: &str) -> Option<usize> {
+        let len = self.proxies.len();
+        if len == 0 {
+            return None;
+        }
+
+        let start = match self.strategy {
+            ProxyStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            ProxyStrategy::PerHost => {
+                let mut hasher = DefaultHasher::new();
+                host.hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+
+        // Skip unhealthy proxies; per-host assignment moves on to the next one in order
+        (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&index| self.proxies[index].healthy.load(Ordering::Relaxed))
+    }
+
+    /// Record a request that got through the proxy
+    pub fn record_success(&self, index: usize) {
+        self.proxies[index].failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Record a failed proxy probe; returns true if the proxy was taken out of rotation
+    pub fn record_failure(&self, index: usize) -> bool {
+        let proxy = &self.proxies[index];
+        let failures = proxy.failures.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if failures >= MAX_PROXY_FAILURES && proxy.healthy.swap(false, Ordering::Relaxed) {
+            warn!(
+                "Proxy {} failed {} times, removing it from rotation ({} left)",
+                proxy.url,
+                failures,
+                self.healthy_count()
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Check whether the proxy itself accepts TCP connections
+    ///
+    /// Used after a connection error to tell a dead proxy from a dead image host.
+    pub async fn probe(&self, index: usize) -> bool {
+        let Ok(url) = Url::parse(&self.proxies[index].url) else {
+            return false;
+        };
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let port = url.port_or_known_default().unwrap_or(1080);
+
+        matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await,
+            Ok(Ok(_))
+        )
+    }
+}
+
+/// Why a proxy wouldn't fetch a URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// The proxy rejected the credentials or the handshake failed, so no URL gets through
+    Proxy,
+    /// The proxy's rules deny this destination (e.g. an ACL), other URLs may get through
+    Destination,
+}
+
+/// Find a proxy refusing a request in the source chain of a request error
+///
+/// Covers CONNECT tunnels the proxy rejected (407 for bad credentials, an ACL deny) and
+/// failed SOCKS handshakes, authentication or rulesets. The proxy's connector errors are
+/// private types, so they are recognised by their messages.
+pub fn refusal(error: &(dyn Error + 'static)) -> Option<Refusal> {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        let message = error.to_string();
+        if PROXY_REFUSALS.contains(&message.as_str()) {
+            return Some(Refusal::Proxy);
+        }
+        if let Some(reason) = message.strip_prefix("tunnel error: ") {
+            return (reason != "failed to create underlying connection")
+                .then_some(Refusal::Destination);
+        }
+        if let Some(reason) = message.strip_prefix("SOCKS error: ") {
+            return (!SOCKS_NON_REFUSALS.contains(&reason)).then_some(Refusal::Destination);
+        }
+        source = error.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(strategy: ProxyStrategy) -> ProxyPool {
+        ProxyPool::new(
+            vec![
+                "http://proxy-a:3128".to_string(),
+                "socks5://proxy-b:1080".to_string(),
+                "socks5h://proxy-c:1080".to_string(),
+            ],
+            strategy,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_invalid_proxies() {
+        assert!(ProxyPool::new(
+            vec!["ftp://proxy:21".to_string()],
+            ProxyStrategy::RoundRobin
+        )
+        .is_err());
+        assert!(ProxyPool::new(vec!["not a url".to_string()], ProxyStrategy::RoundRobin).is_err());
+    }
+
+    #[test]
+    fn test_round_robin_skips_unhealthy() {
+        let pool = pool(ProxyStrategy::RoundRobin);
+        let picks: Vec<_> = (0..3).map(|_| pool.select("a.com").unwrap()).collect();
+        assert_eq!(picks, vec![0, 1, 2]);
+
+        for _ in 0..MAX_PROXY_FAILURES {
+            pool.record_failure(1);
+        }
+        assert_eq!(pool.healthy_count(), 2);
+        assert!((0..6).all(|_| pool.select("a.com") != Some(1)));
+    }
+
+    #[test]
+    fn test_per_host_is_sticky() {
+        let pool = pool(ProxyStrategy::PerHost);
+        let first = pool.select("img.supplier.com").unwrap();
+        assert!((0..5).all(|_| pool.select("img.supplier.com") == Some(first)));
+
+        for _ in 0..MAX_PROXY_FAILURES {
+            pool.record_failure(first);
+        }
+        let failover = pool.select("img.supplier.com").unwrap();
+        assert_ne!(failover, first);
+    }
+
+    #[test]
+    fn test_refusals() {
+        let refused = |message: &str| refusal(&std::io::Error::other(message.to_string()));
+        assert_eq!(
+            refused("tunnel error: proxy authorization required"),
+            Some(Refusal::Proxy)
+        );
+        assert_eq!(
+            refused("SOCKS error: credentials not accepted"),
+            Some(Refusal::Proxy)
+        );
+
+        // Rules denying one destination say nothing about the proxy
+        assert_eq!(
+            refused("tunnel error: unsuccessful"),
+            Some(Refusal::Destination)
+        );
+        assert_eq!(
+            refused("SOCKS error: connection not allowed"),
+            Some(Refusal::Destination)
+        );
+
+        // The proxy being down, or the image host being unreachable through it
+        assert_eq!(
+            refused("tunnel error: failed to create underlying connection"),
+            None
+        );
+        assert_eq!(refused("SOCKS error: host unreachable"), None);
+        assert_eq!(refused("error sending request"), None);
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let pool = pool(ProxyStrategy::RoundRobin);
+        pool.record_failure(0);
+        pool.record_failure(0);
+        pool.record_success(0);
+        pool.record_failure(0);
+        assert_eq!(pool.healthy_count(), 3);
+
+        for index in 0..3 {
+            for _ in 0..MAX_PROXY_FAILURES {
+                pool.record_failure(index);
+            }
+        }
+        assert_eq!(pool.select("a.com"), None);
+    }
+}
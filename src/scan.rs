@@ -0,0 +1,75 @@
+use crate::checker::{CheckResult, UrlChecker};
+use crate::db::Database;
+use crate::key::RowKey;
+use anyhow::Result;
+use futures::stream::{self, Stream};
+use std::collections::HashMap;
+
+/// One batch of a table scan, with a result for every row fetched
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ScanBatch {
+    /// Highest key of the batch; the scan resumes after it
+    pub last_key: RowKey,
+    pub results: Vec<CheckResult>,
+    /// Row key of each result, by `CheckResult::id`
+    keys: HashMap<i64, RowKey>,
+}
+
+impl ScanBatch {
+    /// Row key of one of the batch's results
+    pub fn key(&self, result: &CheckResult) -> &RowKey {
+        &self.keys[&result.id]
+    }
+}
+
+/// Keyset scan of a table's URLs, fetching and checking one batch at a time
+pub struct Scan<'a> {
+    database: &'a Database,
+    checker: &'a UrlChecker,
+    batch_size: i64,
+}
+
+impl<'a> Scan<'a> {
+    pub fn new(database: &'a Database, checker: &'a UrlChecker, batch_size: i64) -> Self {
+        Self {
+            database,
+            checker,
+            batch_size,
+        }
+    }
+
+    /// Checked batches of the rows after `after` (all rows without it), in key order,
+    /// until the table is exhausted
+    ///
+    /// The next batch is only fetched once the stream is polled again, so a consumer can
+    /// stop (and save a checkpoint) between batches. Results are identified by the row's
+    /// id for single integer keys, and by the row's position in the scan otherwise.
+    pub fn batches(&self, after: Option<RowKey>) -> impl Stream<Item = Result<ScanBatch>> + 'a {
+        let (database, checker, batch_size) = (self.database, self.checker, self.batch_size);
+        stream::try_unfold((after, 0), move |(after, position)| async move {
+            let rows = database.fetch_batch(after.as_ref(), batch_size).await?;
+            let Some((last_key, _)) = rows.last() else {
+                return Ok(None);
+            };
+            let last_key = last_key.clone();
+
+            let mut keys = HashMap::with_capacity(rows.len());
+            let mut urls = Vec::with_capacity(rows.len());
+            for (index, (key, url)) in rows.into_iter().enumerate() {
+                let id = key.as_int().unwrap_or(position + index as i64 + 1);
+                keys.insert(id, key);
+                urls.push((id, url));
+            }
+            let position = position + urls.len() as i64;
+
+            let results = checker.check_batch(urls).await;
+            let batch = ScanBatch {
+                last_key: last_key.clone(),
+                results,
+                keys,
+            };
+            Ok(Some((batch, (Some(last_key), position))))
+        })
+    }
+}
@@ -0,0 +1,394 @@
+use crate::checker::{CheckResult, Outcome};
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::HeaderMap;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Most body bytes handed to the script
+pub const SCRIPT_BODY_LIMIT: usize = 64 * 1024;
+
+/// Operations between two checks of the call's deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+thread_local! {
+    /// Deadline of the script call running on this thread
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// What the script decided for a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScriptVerdict {
+    /// Keep the built-in classification
+    Default,
+    Valid(Option<String>),
+    Broken(Option<String>),
+    /// Broken for now, but checked again in the next retry phase
+    Retry(Option<String>),
+    Blocked(Option<String>),
+    /// Repeat a HEAD request as GET and call the script again with the body
+    FetchBody,
+}
+
+impl ScriptVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptVerdict::Default => "default",
+            ScriptVerdict::Valid(_) => "valid",
+            ScriptVerdict::Broken(_) => "broken",
+            ScriptVerdict::Retry(_) => "retry",
+            ScriptVerdict::Blocked(_) => "blocked",
+            ScriptVerdict::FetchBody => "fetch_body",
+        }
+    }
+
+    /// Override a built-in result with the verdict
+    pub fn apply(self, mut result: CheckResult) -> CheckResult {
+        let name = self.as_str();
+        let (is_valid, outcome, is_retryable, reason, default_reason) = match self {
+            ScriptVerdict::Default | ScriptVerdict::FetchBody => return result,
+            ScriptVerdict::Valid(reason) => (true, Outcome::Valid, false, reason, None),
+            ScriptVerdict::Broken(reason) => (
+                false,
+                Outcome::Broken,
+                false,
+                reason,
+                Some("Classified as broken by script"),
+            ),
+            ScriptVerdict::Retry(reason) => (
+                false,
+                Outcome::Broken,
+                true,
+                reason,
+                Some("Retry requested by script"),
+            ),
+            ScriptVerdict::Blocked(reason) => (
+                false,
+                Outcome::Blocked,
+                false,
+                reason,
+                Some("Classified as blocked by script"),
+            ),
+        };
+
+        result.is_valid = is_valid;
+        result.outcome = outcome;
+        result.is_retryable = is_retryable;
+        result.error = reason.or(default_reason.map(str::to_string));
+        result.error_kind = None;
+        result
+            .annotations
+            .insert("script".to_string(), name.to_string());
+        result
+    }
+}
+
+/// Response metadata handed to the script
+pub struct ScriptInput<'a> {
+    pub url: &'a str,
+    pub method: &'a str,
+    pub status: u16,
+    pub headers: &'a HeaderMap,
+    /// First bytes of the body (empty for HEAD requests)
+    pub body: &'a [u8],
+    /// Retry attempt (0 = initial check)
+    pub attempt: u32,
+    /// Classification by the built-in rules
+    pub result: &'a CheckResult,
+}
+
+impl ScriptInput<'_> {
+    /// The `response` map the script's `classify` function receives
+    fn to_map(&self) -> Map {
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in self.headers {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+        let host = reqwest::Url::parse(self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let mut map = Map::new();
+        map.insert("url".into(), self.url.into());
+        map.insert("host".into(), host.into());
+        map.insert("method".into(), self.method.into());
+        map.insert("status".into(), Dynamic::from_int(self.status.into()));
+        map.insert(
+            "headers".into(),
+            Dynamic::from_map(
+                headers
+                    .into_iter()
+                    .map(|(name, value)| (name.into(), value.into()))
+                    .collect(),
+            ),
+        );
+        map.insert(
+            "body".into(),
+            String::from_utf8_lossy(self.body).into_owned().into(),
+        );
+        map.insert("attempt".into(), Dynamic::from_int(self.attempt.into()));
+        map.insert("category".into(), self.result.outcome.as_str().into());
+        map.insert("valid".into(), self.result.is_valid.into());
+        map.insert(
+            "error".into(),
+            self.result
+                .error
+                .clone()
+                .map(Dynamic::from)
+                .unwrap_or(Dynamic::UNIT),
+        );
+        map
+    }
+}
+
+/// User script deciding how responses are classified
+///
+/// The script defines `fn classify(response)` and is called after each response with
+/// its metadata and the built-in classification. It runs sandboxed: no modules, no
+/// `eval`, bounded sizes and nesting, and a time limit per call.
+pub struct ClassifyScript {
+    engine: Engine,
+    ast: AST,
+    time_limit: Duration,
+}
+
+impl ClassifyScript {
+    pub fn load(path: impl AsRef<Path>, time_limit: Duration) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read classification script {:?}", path))?;
+        Self::compile(&source, time_limit)
+            .with_context(|| format!("Invalid classification script {:?}", path))
+    }
+
+    pub fn compile(source: &str, time_limit: Duration) -> Result<Self> {
+        let engine = sandboxed_engine();
+        let ast = engine.compile(source).map_err(|e| anyhow!("{}", e))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "classify" && f.params.len() == 1)
+        {
+            return Err(anyhow!("The script must define fn classify(response)"));
+        }
+        Ok(Self {
+            engine,
+            ast,
+            time_limit,
+        })
+    }
+
+    /// Call the script for a response
+    pub fn classify(&self, input: &ScriptInput<'_>) -> Result<ScriptVerdict> {
+        let response = input.to_map();
+
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.time_limit)));
+        let value =
+            self.engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "classify", (response,));
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        match value {
+            Ok(value) => parse_verdict(value),
+            Err(e) if matches!(*e, EvalAltResult::ErrorTerminated(..)) => Err(anyhow!(
+                "script exceeded its time limit of {:?}",
+                self.time_limit
+            )),
+            Err(e) => Err(anyhow!("script error: {}", e)),
+        }
+    }
+}
+
+/// Engine without access to files or other scripts, and with bounded resources
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1024 * 1024)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000);
+    engine.on_print(|text| debug!("Classification script: {}", text));
+    engine.on_debug(|text, _, _| debug!("Classification script: {}", text));
+    engine.on_progress(|operations| {
+        if operations % DEADLINE_CHECK_INTERVAL != 0 {
+            return None;
+        }
+        let expired = DEADLINE.with(|deadline| {
+            deadline
+                .get()
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        });
+        expired.then(|| Dynamic::from("time limit exceeded"))
+    });
+    engine
+}
+
+/// Verdict from the script's return value: a name, a map with `verdict` and `reason`,
+/// or nothing to keep the built-in classification
+fn parse_verdict(value: Dynamic) -> Result<ScriptVerdict> {
+    if value.is_unit() {
+        return Ok(ScriptVerdict::Default);
+    }
+
+    let (name, reason) = if value.is_map() {
+        let map = value.cast::<Map>();
+        let text = |key: &str| {
+            map.get(key)
+                .and_then(|value| value.clone().into_string().ok())
+        };
+        let name =
+            text("verdict").ok_or_else(|| anyhow!("the returned map needs a `verdict` string"))?;
+        (name, text("reason"))
+    } else {
+        let name = value.into_string().map_err(|type_name| {
+            anyhow!(
+                "classify returned a {}, expected a verdict string or map",
+                type_name
+            )
+        })?;
+        (name, None)
+    };
+
+    Ok(match name.as_str() {
+        "default" => ScriptVerdict::Default,
+        "valid" => ScriptVerdict::Valid(reason),
+        "broken" => ScriptVerdict::Broken(reason),
+        "retry" => ScriptVerdict::Retry(reason),
+        "blocked" => ScriptVerdict::Blocked(reason),
+        "fetch_body" => ScriptVerdict::FetchBody,
+        other => return Err(anyhow!("unknown verdict {:?}", other)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, SERVER};
+
+    const LIMIT: Duration = Duration::from_millis(200);
+
+    fn result(status: u16) -> CheckResult {
+        CheckResult {
+            id: 1,
+            url: "https://img.supplier-x.com/a.jpg".to_string(),
+            is_valid: false,
+            status_code: Some(status),
+            error: Some(format!("HTTP {}", status)),
+            is_retryable: false,
+            outcome: Outcome::Broken,
+            timeout: None,
+            error_kind: None,
+            exchanges: Vec::new(),
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    fn classify(script: &ClassifyScript, method: &str, body: &[u8]) -> Result<ScriptVerdict> {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, HeaderValue::from_static("AmazonS3"));
+        script.classify(&ScriptInput {
+            url: "https://img.supplier-x.com/a.jpg",
+            method,
+            status: 403,
+            headers: &headers,
+            body,
+            attempt: 0,
+            result: &result(403),
+        })
+    }
+
+    #[test]
+    fn test_supplier_rule() {
+        let script = ClassifyScript::compile(
+            r#"
+            fn classify(response) {
+                if response.host.ends_with("supplier-x.com") && response.status == 403 {
+                    if response.method == "HEAD" { return "fetch_body"; }
+                    if response.body.contains("AccessDenied") {
+                        return #{ verdict: "broken", reason: "S3 AccessDenied" };
+                    }
+                    return "valid";
+                }
+            }
+            "#,
+            LIMIT,
+        )
+        .unwrap();
+
+        assert_eq!(
+            classify(&script, "HEAD", b"").unwrap(),
+            ScriptVerdict::FetchBody
+        );
+        assert_eq!(
+            classify(&script, "GET", b"<Error><Code>AccessDenied</Code></Error>").unwrap(),
+            ScriptVerdict::Broken(Some("S3 AccessDenied".to_string()))
+        );
+        let verdict = classify(&script, "GET", b"\x89PNG").unwrap();
+        assert_eq!(verdict, ScriptVerdict::Valid(None));
+
+        let applied = verdict.apply(result(403));
+        assert!(applied.is_valid);
+        assert_eq!(applied.outcome, Outcome::Valid);
+        assert_eq!(applied.annotations["script"], "valid");
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let verdict = |body: &str| {
+            let script =
+                ClassifyScript::compile(&format!("fn classify(response) {{ {} }}", body), LIMIT)
+                    .unwrap();
+            classify(&script, "GET", b"")
+        };
+        assert_eq!(verdict("").unwrap(), ScriptVerdict::Default);
+        assert_eq!(verdict(r#""retry""#).unwrap(), ScriptVerdict::Retry(None));
+        assert_eq!(
+            verdict(r#"if response.headers.server == "AmazonS3" { "blocked" }"#).unwrap(),
+            ScriptVerdict::Blocked(None)
+        );
+        assert!(verdict(r#""maybe""#).is_err());
+        assert!(verdict("42").is_err());
+        assert!(verdict(r#"#{ reason: "no verdict" }"#).is_err());
+
+        let retry = ScriptVerdict::Retry(None).apply(result(403));
+        assert!(retry.is_retryable);
+        assert_eq!(retry.error.as_deref(), Some("Retry requested by script"));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        // The entry point is required and the script must parse
+        assert!(ClassifyScript::compile("fn other(x) { x }", LIMIT).is_err());
+        assert!(ClassifyScript::compile("fn classify(response) {", LIMIT).is_err());
+        // No eval and no modules
+        assert!(ClassifyScript::compile(r#"fn classify(r) { eval("1") }"#, LIMIT).is_err());
+        let import = ClassifyScript::compile(
+            r#"fn classify(r) { import "/etc/passwd" as secrets; "valid" }"#,
+            LIMIT,
+        )
+        .unwrap();
+        assert!(classify(&import, "GET", b"").is_err());
+
+        // Runaway scripts are stopped after the time limit
+        let endless = ClassifyScript::compile("fn classify(r) { loop {} }", LIMIT).unwrap();
+        let started = Instant::now();
+        let error = classify(&endless, "GET", b"").unwrap_err();
+        assert!(error.to_string().contains("time limit"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Result};
+use hotel_broken_image_checker::mock_server::MockServer;
+
+/// Check the scripted scenarios of the local mock server and report each classification
+pub async fn run() -> Result<()> {
+    let server = MockServer::start().await?;
+    println!("Self-test against {}", server.url("/"));
+    println!();
+
+    let results = server.run_scenarios().await?;
+    let mut failed = 0;
+    for (scenario, result) in &results {
+        match scenario.verify(result) {
+            Ok(()) => println!(
+                "  PASS  {:<18} {:<18} {} request(s)  {}",
+                scenario.name,
+                result.outcome.as_str(),
+                server.hits(scenario.path),
+                scenario.description
+            ),
+            Err(mismatch) => {
+                failed += 1;
+                println!(
+                    "  FAIL  {:<18} {} ({})",
+                    scenario.name,
+                    mismatch,
+                    result.error.as_deref().unwrap_or("no error")
+                );
+            }
+        }
+    }
+
+    println!();
+    println!("{} scenarios, {} failed", results.len(), failed);
+    if failed > 0 {
+        return Err(anyhow!("{} self-test scenarios failed", failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use reqwest::Url;
+
+/// Pre-signing scheme detected from a URL's query parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// AWS Signature V4 (`X-Amz-Date` + `X-Amz-Expires`) or legacy S3 V2 (`AWSAccessKeyId` + `Expires`)
+    Aws,
+    /// CloudFront canned (`Expires`) or custom (`Policy`) signed URL
+    CloudFront,
+    /// Google Cloud Storage V4 (`X-Goog-Date` + `X-Goog-Expires`) or V2 (`GoogleAccessId` + `Expires`)
+    Google,
+}
+
+impl SignatureScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignatureScheme::Aws => "AWS",
+            SignatureScheme::CloudFront => "CloudFront",
+            SignatureScheme::Google => "Google",
+        }
+    }
+}
+
+/// Signature found on a pre-signed URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlSignature {
+    pub scheme: SignatureScheme,
+    /// When the signature stops being valid, if it could be determined
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl UrlSignature {
+    /// Check if the signature has expired at the given time
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Human readable description for reports
+    pub fn describe(&self) -> String {
+        match self.expires_at {
+            Some(expires_at) => format!(
+                "{} signature expired at {}",
+                self.scheme.as_str(),
+                expires_at.to_rfc3339()
+            ),
+            None => format!("{} signature rejected", self.scheme.as_str()),
+        }
+    }
+}
+
+/// Detect a pre-signed URL and work out when its signature expires
+pub fn detect(url: &str) -> Option<UrlSignature> {
+    let parsed = Url::parse(url).ok()?;
+    let params: Vec<(String, String)> = parsed
+        .query_pairs()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v.into_owned()))
+        .collect();
+    let get = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    if let Some(date) = get("x-amz-date") {
+        return Some(UrlSignature {
+            scheme: SignatureScheme::Aws,
+            expires_at: signed_at_plus(date, get("x-amz-expires")),
+        });
+    }
+
+    if let Some(date) = get("x-goog-date") {
+        return Some(UrlSignature {
+            scheme: SignatureScheme::Google,
+            expires_at: signed_at_plus(date, get("x-goog-expires")),
+        });
+    }
+
+    if get("key-pair-id").is_some() {
+        let expires_at = match get("expires") {
+            Some(expires) => parse_epoch(expires),
+            None => get("policy").and_then(cloudfront_policy_expiry),
+        };
+        return Some(UrlSignature {
+            scheme: SignatureScheme::CloudFront,
+            expires_at,
+        });
+    }
+
+    // Legacy query string authentication (S3 SigV2 and GCS V2) uses an epoch `Expires`
+    if let Some(expires) = get("expires") {
+        let scheme = if get("awsaccesskeyid").is_some() {
+            SignatureScheme::Aws
+        } else if get("googleaccessid").is_some() {
+            SignatureScheme::Google
+        } else {
+            return None;
+        };
+        return Some(UrlSignature {
+            scheme,
+            expires_at: parse_epoch(expires),
+        });
+    }
+
+    None
+}
+
+/// Expiry from an ISO 8601 basic timestamp (`20240115T103000Z`) plus a lifetime in seconds
+fn signed_at_plus(date: &str, expires: Option<&str>) -> Option<DateTime<Utc>> {
+    let signed_at = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").ok()?;
+    let lifetime: i64 = expires?.parse().ok()?;
+    Some(Utc.from_utc_datetime(&signed_at) + Duration::seconds(lifetime))
+}
+
+fn parse_epoch(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(value.parse().ok()?, 0)
+}
+
+/// Read `DateLessThan` from a CloudFront custom policy
+///
+/// CloudFront encodes the policy as base64 with `+`, `=` and `/` replaced by `-`, `_` and `~`.
+fn cloudfront_policy_expiry(policy: &str) -> Option<DateTime<Utc>> {
+    let encoded: String = policy
+        .chars()
+        .map(|c| match c {
+            '-' => '+',
+            '_' => '=',
+            '~' => '/',
+            other => other,
+        })
+        .collect();
+    let decoded = STANDARD.decode(encoded).ok()?;
+    let policy: serde_json::Value = serde_json::from_slice(&decoded).ok()?;
+
+    policy["Statement"]
+        .as_array()?
+        .iter()
+        .filter_map(|statement| statement["Condition"]["DateLessThan"]["AWS:EpochTime"].as_i64())
+        .min()
+        .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_aws_v4() {
+        let sig = detect(
+            "https://bucket.s3.amazonaws.com/a.jpg?X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Date=20240115T103000Z&X-Amz-Expires=3600&X-Amz-Signature=abc",
+        )
+        .unwrap();
+        assert_eq!(sig.scheme, SignatureScheme::Aws);
+        assert_eq!(
+            sig.expires_at,
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 11, 30, 0).unwrap())
+        );
+        assert!(sig.is_expired_at(Utc::now()));
+    }
+
+    #[test]
+    fn test_detect_google_and_cloudfront() {
+        let sig = detect(
+            "https://storage.googleapis.com/b/a.jpg?X-Goog-Date=20990101T000000Z&X-Goog-Expires=60",
+        )
+        .unwrap();
+        assert_eq!(sig.scheme, SignatureScheme::Google);
+        assert!(!sig.is_expired_at(Utc::now()));
+
+        let sig =
+            detect("https://d1.cloudfront.net/a.jpg?Expires=1700000000&Signature=x&Key-Pair-Id=K1")
+                .unwrap();
+        assert_eq!(sig.scheme, SignatureScheme::CloudFront);
+        assert_eq!(sig.expires_at, DateTime::from_timestamp(1_700_000_000, 0));
+    }
+
+    #[test]
+    fn test_cloudfront_custom_policy() {
+        let policy = STANDARD
+            .encode(r#"{"Statement":[{"Resource":"*","Condition":{"DateLessThan":{"AWS:EpochTime":1700000000}}}]}"#)
+            .replace('+', "-")
+            .replace('=', "_")
+            .replace('/', "~");
+        let url = format!(
+            "https://d1.cloudfront.net/a.jpg?Policy={}&Signature=x&Key-Pair-Id=K1",
+            policy
+        );
+        let sig = detect(&url).unwrap();
+        assert_eq!(sig.expires_at, DateTime::from_timestamp(1_700_000_000, 0));
+    }
+
+    #[test]
+    fn test_unsigned_urls() {
+        assert!(detect("https://example.com/a.jpg").is_none());
+        assert!(detect("https://example.com/a.jpg?Expires=1700000000").is_none());
+        assert!(detect("not a url").is_none());
+    }
+}
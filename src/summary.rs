@@ -0,0 +1,143 @@
+use crate::checker::{CheckResult, Outcome};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// Maximum number of hosts listed per category in the run summary
+const MAX_HOSTS_LISTED: usize = 20;
+
+/// Counts of reported URLs per outcome, and per host for host-level problems
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Reported URLs per outcome category (e.g. "data_uri", "blocked")
+    #[serde(default)]
+    pub outcome_counts: BTreeMap<String, i64>,
+
+    /// Reported URLs per host, for outcomes that are a property of the host
+    #[serde(default)]
+    pub host_counts: BTreeMap<String, BTreeMap<String, i64>>,
+}
+
+impl RunSummary {
+    /// Record a reported result
+    pub fn record(&mut self, result: &CheckResult) {
+        let outcome = result.outcome.as_str().to_string();
+        *self.outcome_counts.entry(outcome.clone()).or_insert(0) += 1;
+
+        if is_host_level(result.outcome) {
+            let host = Url::parse(&result.url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string))
+                .unwrap_or_else(|| "(unknown)".to_string());
+            *self
+                .host_counts
+                .entry(outcome)
+                .or_default()
+                .entry(host)
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Add the counts of another summary (e.g. of one tenant to the run's)
+    pub fn merge(&mut self, other: &RunSummary) {
+        for (outcome, count) in &other.outcome_counts {
+            *self.outcome_counts.entry(outcome.clone()).or_insert(0) += count;
+        }
+        for (outcome, hosts) in &other.host_counts {
+            let counts = self.host_counts.entry(outcome.clone()).or_default();
+            for (host, count) in hosts {
+                *counts.entry(host.clone()).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// Number of reported URLs with a given outcome
+    pub fn count(&self, outcome: Outcome) -> i64 {
+        self.outcome_counts
+            .get(outcome.as_str())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Log the per-category and per-host counts
+    pub fn log(&self) {
+        if self.outcome_counts.is_empty() {
+            return;
+        }
+
+        info!("Reported URLs by category:");
+        for (outcome, count) in &self.outcome_counts {
+            info!("  {}: {}", outcome, count);
+        }
+
+        for (outcome, hosts) in &self.host_counts {
+            let mut hosts: Vec<(&String, &i64)> = hosts.iter().collect();
+            hosts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+            info!("Hosts with {} URLs ({} hosts):", outcome, hosts.len());
+            for (host, count) in hosts.iter().take(MAX_HOSTS_LISTED) {
+                info!("  {}: {}", host, count);
+            }
+            if hosts.len() > MAX_HOSTS_LISTED {
+                info!("  ... and {} more", hosts.len() - MAX_HOSTS_LISTED);
+            }
+        }
+    }
+}
+
+/// Outcomes caused by the host rather than the individual image
+fn is_host_level(outcome: Outcome) -> bool {
+    matches!(
+        outcome,
+        Outcome::Blocked | Outcome::DomainNotFound | Outcome::BlockedByPolicy | Outcome::TlsError
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str, outcome: Outcome) -> CheckResult {
+        CheckResult {
+            id: 1,
+            url: url.to_string(),
+            is_valid: false,
+            status_code: Some(403),
+            error: None,
+            is_retryable: false,
+            outcome,
+            timeout: None,
+            error_kind: None,
+            exchanges: Vec::new(),
+            annotations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut summary = RunSummary::default();
+        summary.record(&result("https://a.example.com/1.jpg", Outcome::Blocked));
+        summary.record(&result("https://a.example.com/2.jpg", Outcome::Blocked));
+        summary.record(&result("https://b.example.com/1.jpg", Outcome::Broken));
+
+        assert_eq!(summary.count(Outcome::Blocked), 2);
+        assert_eq!(summary.count(Outcome::Broken), 1);
+        assert_eq!(summary.host_counts["blocked"]["a.example.com"], 2);
+        assert!(!summary.host_counts.contains_key("broken"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut total = RunSummary::default();
+        total.record(&result("https://a.example.com/1.jpg", Outcome::Blocked));
+        let mut tenant = RunSummary::default();
+        tenant.record(&result("https://a.example.com/2.jpg", Outcome::Blocked));
+        tenant.record(&result("https://b.example.com/1.jpg", Outcome::Broken));
+
+        total.merge(&tenant);
+        assert_eq!(total.count(Outcome::Blocked), 2);
+        assert_eq!(total.count(Outcome::Broken), 1);
+        assert_eq!(total.host_counts["blocked"]["a.example.com"], 2);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::db::Dialect;
+use anyhow::{anyhow, Result};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::Row;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// Where the tenant schemas of a multi-tenant (django-tenants style) database are listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantSource {
+    /// Every schema holding the table
+    Catalog,
+    /// A column of a tenants table, e.g. `customers_client.schema_name`
+    Table { table: String, column: String },
+}
+
+/// Tenant schemas holding `table`, in the order of the source, without `exclude`
+///
+/// Tenants listed in a tenants table whose schema lacks the table are skipped with a
+/// warning. Only PostgreSQL has tenant schemas.
+pub async fn discover_tenants(
+    db_url: &str,
+    table: &str,
+    source: &TenantSource,
+    exclude: &[String],
+) -> Result<Vec<String>> {
+    let dialect = Dialect::from_url(db_url)?;
+    if dialect != Dialect::Postgres {
+        return Err(anyhow!(
+            "Tenant schemas are only supported on PostgreSQL, not {}",
+            dialect
+        ));
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(db_url)
+        .await?;
+
+    // Schemas with a table (or partitioned table) of that name
+    let with_table: Vec<String> = sqlx::query(
+        "SELECT n.nspname::text FROM pg_catalog.pg_class c \
+         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+         WHERE c.relname = $1 AND c.relkind IN ('r', 'p') \
+         AND n.nspname <> 'information_schema' AND n.nspname NOT LIKE 'pg\\_%' \
+         ORDER BY n.nspname",
+    )
+    .bind(table)
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|row| row.get(0))
+    .collect();
+
+    let listed = match source {
+        TenantSource::Catalog => None,
+        TenantSource::Table {
+            table: tenants_table,
+            column,
+        } => {
+            let (schema, name) = match tenants_table.split_once('.') {
+                Some((schema, name)) => (Some(schema), name),
+                None => (None, tenants_table.as_str()),
+            };
+            let query = format!(
+                "SELECT DISTINCT {column}::text FROM {table} WHERE {column} IS NOT NULL ORDER BY 1",
+                column = Dialect::Postgres.quote(column),
+                table = Dialect::Postgres.quote_qualified(schema, name),
+            );
+            let rows = sqlx::query(&query).fetch_all(&pool).await.map_err(|e| {
+                anyhow!(
+                    "Failed to read tenant schemas from {}.{}: {}",
+                    tenants_table,
+                    column,
+                    e
+                )
+            })?;
+            Some(rows.into_iter().map(|row| row.get(0)).collect())
+        }
+    };
+    pool.close().await;
+
+    let tenants = select_tenants(listed, &with_table, exclude);
+    info!(
+        "Found {} tenant schemas with table {}",
+        tenants.len(),
+        table
+    );
+    Ok(tenants)
+}
+
+/// Listed tenants (all schemas with the table without a list) that have the table,
+/// minus the excluded ones
+fn select_tenants(
+    listed: Option<Vec<String>>,
+    with_table: &[String],
+    exclude: &[String],
+) -> Vec<String> {
+    let has_table: HashSet<&str> = with_table.iter().map(String::as_str).collect();
+    let listed = listed.unwrap_or_else(|| with_table.to_vec());
+
+    listed
+        .into_iter()
+        .filter(|schema| !exclude.contains(schema))
+        .filter(|schema| {
+            let found = has_table.contains(schema.as_str());
+            if !found {
+                warn!("Skipping tenant {}: its schema has no such table", schema);
+            }
+            found
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_tenants() {
+        let with_table = names(&["public", "tenant_a", "tenant_b"]);
+
+        // From the catalog: every schema with the table
+        assert_eq!(
+            select_tenants(None, &with_table, &names(&["public"])),
+            names(&["tenant_a", "tenant_b"])
+        );
+
+        // From a tenants table: in its order, skipping schemas without the table
+        let listed = names(&["tenant_b", "tenant_c", "tenant_a"]);
+        assert_eq!(
+            select_tenants(Some(listed), &with_table, &[]),
+            names(&["tenant_b", "tenant_a"])
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use rustls::{AlertDescription, CertificateError};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Kind of TLS handshake failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsProblem {
+    /// Certificate expired (or is not valid yet)
+    Expired,
+    /// Certificate was issued for another host name
+    HostnameMismatch,
+    /// Certificate chain doesn't lead to a trusted root (incl. self-signed)
+    UnknownCa,
+    /// No common TLS version or cipher suite, or the server doesn't speak TLS at all
+    ProtocolMismatch,
+    /// Any other handshake failure
+    Other,
+}
+
+impl TlsProblem {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TlsProblem::Expired => "TLS certificate expired",
+            TlsProblem::HostnameMismatch => "TLS certificate does not match host name",
+            TlsProblem::UnknownCa => "TLS certificate issued by unknown CA",
+            TlsProblem::ProtocolMismatch => "TLS protocol mismatch",
+            TlsProblem::Other => "TLS handshake failed",
+        }
+    }
+
+    /// Find a TLS failure in the source chain of a request error
+    pub fn find(error: &(dyn Error + 'static)) -> Option<Self> {
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if let Some(tls) = error.downcast_ref::<rustls::Error>() {
+                return Some(Self::from_rustls(tls));
+            }
+            // io::Error::source() skips the error it wraps, which is where rustls puts its own
+            source = match error
+                .downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+            {
+                Some(inner) => Some(inner as &(dyn Error + 'static)),
+                None => error.source(),
+            };
+        }
+        None
+    }
+
+    fn from_rustls(error: &rustls::Error) -> Self {
+        match error {
+            rustls::Error::InvalidCertificate(cert) => match cert {
+                CertificateError::Expired
+                | CertificateError::ExpiredContext { .. }
+                | CertificateError::NotValidYet
+                | CertificateError::NotValidYetContext { .. } => TlsProblem::Expired,
+                CertificateError::NotValidForName
+                | CertificateError::NotValidForNameContext { .. } => TlsProblem::HostnameMismatch,
+                CertificateError::UnknownIssuer => TlsProblem::UnknownCa,
+                _ => TlsProblem::Other,
+            },
+            rustls::Error::PeerIncompatible(_)
+            | rustls::Error::InvalidMessage(_)
+            | rustls::Error::AlertReceived(
+                AlertDescription::ProtocolVersion
+                | AlertDescription::HandshakeFailure
+                | AlertDescription::InsufficientSecurity,
+            ) => TlsProblem::ProtocolMismatch,
+            _ => TlsProblem::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_through_io_error() {
+        let io = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(CertificateError::Expired),
+        );
+        assert_eq!(TlsProblem::find(&io), Some(TlsProblem::Expired));
+
+        let io = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer),
+        );
+        assert_eq!(TlsProblem::find(&io), Some(TlsProblem::UnknownCa));
+
+        // hyper wraps the rustls error in another io::Error
+        let io = std::io::Error::other(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(CertificateError::NotValidForName),
+        ));
+        assert_eq!(TlsProblem::find(&io), Some(TlsProblem::HostnameMismatch));
+
+        let io = std::io::Error::other("connection reset");
+        assert_eq!(TlsProblem::find(&io), None);
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            TlsProblem::from_rustls(&rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName
+            )),
+            TlsProblem::HostnameMismatch
+        );
+        assert_eq!(
+            TlsProblem::from_rustls(&rustls::Error::AlertReceived(
+                AlertDescription::ProtocolVersion
+            )),
+            TlsProblem::ProtocolMismatch
+        );
+        assert_eq!(
+            TlsProblem::from_rustls(&rustls::Error::DecryptError),
+            TlsProblem::Other
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{
+    HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE,
+};
+use reqwest::{Method, Version};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// Number of body bytes kept as evidence of what a URL returned
+pub const BODY_PREFIX_LEN: usize = 1024;
+
+/// One HTTP request and its response (or failure)
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    /// Retry attempt the request belongs to (0 = initial check)
+    pub attempt: u32,
+    pub method: Method,
+    pub url: String,
+    pub request_headers: HeaderMap,
+    pub started_at: DateTime<Utc>,
+    /// Time until the response headers arrived (or the request failed)
+    pub elapsed: Duration,
+    pub status: Option<u16>,
+    pub http_version: Option<Version>,
+    pub response_headers: HeaderMap,
+    /// First bytes of the body, if it was read (HEAD responses have none)
+    pub body_prefix: Option<Vec<u8>>,
+    pub error: Option<String>,
+}
+
+/// A step of a traced check
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    /// A decision or observation, in plain words
+    Step(String),
+    /// A request that was sent
+    Exchange(Box<Exchange>),
+}
+
+/// Step-by-step record of how a URL was checked, for the `explain` subcommand
+#[derive(Debug, Default)]
+pub struct Trace {
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl Trace {
+    pub fn step(&self, step: impl Into<String>) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(TraceEvent::Step(step.into()));
+    }
+
+    pub fn exchange(&self, exchange: Exchange) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(TraceEvent::Exchange(Box::new(exchange)));
+    }
+
+    /// Attach the first bytes of the body to the last recorded exchange
+    pub fn body_prefix(&self, bytes: &[u8]) {
+        let mut events = self.events.lock().unwrap();
+        let last = events.iter_mut().rev().find_map(|event| match event {
+            TraceEvent::Exchange(exchange) => Some(exchange),
+            TraceEvent::Step(_) => None,
+        });
+        if let Some(exchange) = last {
+            exchange.body_prefix = Some(bytes[..bytes.len().min(BODY_PREFIX_LEN)].to_vec());
+        }
+    }
+
+    pub fn into_events(self) -> Vec<TraceEvent> {
+        self.events.into_inner().unwrap()
+    }
+
+    /// Recorded requests, without the steps
+    pub fn into_exchanges(self) -> Vec<Exchange> {
+        self.into_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                TraceEvent::Exchange(exchange) => Some(*exchange),
+                TraceEvent::Step(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Whether a header carries credentials that must not be printed or exported
+pub fn is_sensitive(name: &HeaderName) -> bool {
+    [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE].contains(name)
+}
+
+/// Time the TCP connect and, for https, the TLS handshake to a host
+///
+/// Uses a fresh connection outside the HTTP client, whose pooled connections don't
+/// expose these timings.
+pub async fn connect_timing(
+    host: &str,
+    addr: SocketAddr,
+    tls: bool,
+    timeout: Duration,
+) -> Vec<String> {
+    let mut steps = Vec::new();
+
+    let start = Instant::now();
+    let stream = match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            steps.push(format!("TCP connect to {} failed: {}", addr, e));
+            return steps;
+        }
+        Err(_) => {
+            steps.push(format!(
+                "TCP connect to {} timed out after {:?}",
+                addr, timeout
+            ));
+            return steps;
+        }
+    };
+    steps.push(format!("TCP connect to {}: {:?}", addr, start.elapsed()));
+
+    if !tls {
+        return steps;
+    }
+
+    let Ok(server_name) = ServerName::try_from(host.to_string()) else {
+        steps.push(format!(
+            "TLS handshake skipped: invalid server name {}",
+            host
+        ));
+        return steps;
+    };
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, connector.connect(server_name, stream)).await {
+        Ok(Ok(stream)) => {
+            let (_, session) = stream.get_ref();
+            steps.push(format!(
+                "TLS handshake: {:?} ({:?}, {:?})",
+                start.elapsed(),
+                session.protocol_version(),
+                session.negotiated_cipher_suite().map(|s| s.suite())
+            ));
+        }
+        Ok(Err(e)) => steps.push(format!("TLS handshake failed: {}", e)),
+        Err(_) => steps.push(format!("TLS handshake timed out after {:?}", timeout)),
+    }
+
+    steps
+}
+
+/// Describe what the first bytes of a response body look like
+pub fn sniff(bytes: &[u8]) -> String {
+    match imagesize::image_type(bytes) {
+        Ok(image_type) => match imagesize::blob_size(bytes) {
+            Ok(size) => format!("{:?} image, {}x{}", image_type, size.width, size.height),
+            Err(_) => format!("{:?} image", image_type),
+        },
+        Err(_) => {
+            let text = String::from_utf8_lossy(&bytes[..bytes.len().min(64)]);
+            if text.trim_start().starts_with('<') {
+                "HTML or XML, not an image".to_string()
+            } else {
+                "not a known image format".to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        let png = base64_png();
+        assert_eq!(sniff(&png), "Png image, 1x1");
+        assert_eq!(
+            sniff(b"<!DOCTYPE html><html>Not found</html>"),
+            "HTML or XML, not an image"
+        );
+        assert_eq!(sniff(b"\x00\x01garbage"), "not a known image format");
+    }
+
+    fn base64_png() -> Vec<u8> {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==")
+            .unwrap()
+    }
+}
@@ -0,0 +1,805 @@
+use crate::checker::{self, CheckContext, CheckResult, Outcome};
+use crate::data_uri;
+use crate::signed_url;
+use crate::trace;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{Method, Response};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Most bytes of a body read for content stages
+const MAX_CONTENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Decision of one validation stage
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Verdict {
+    /// Go on with the next stage
+    Continue,
+    /// Stop here; the result as it stands is final
+    Stop,
+    /// Stop here; the URL is broken for the given reason
+    Reject(String),
+}
+
+/// One stage of the validation pipeline
+///
+/// Stages run in order on the same [`Inspection`], and can read the result of earlier
+/// stages, fetch the body and annotate the result before deciding.
+pub trait Validator: Send + Sync {
+    /// Name shown in traces and used as the prefix of rejection reasons
+    fn name(&self) -> &str;
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict>;
+}
+
+/// Built-in validation stages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ValidatorKind {
+    /// Only http(s) URLs are requested; inline data URIs are validated locally
+    Scheme,
+    /// The URL answers with a usable response (status, WAF, hotlink and retry rules)
+    Reachability,
+    /// The body is served with an image/* Content-Type
+    ContentType,
+    /// The body starts with the signature of a known image format
+    MagicBytes,
+    /// The image is at least --min-width x --min-height pixels
+    Dimensions,
+    /// The body is not one of the known placeholder images (--placeholder-hash)
+    PlaceholderHash,
+}
+
+/// Settings of the built-in content stages
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ValidatorSettings {
+    pub min_width: usize,
+    pub min_height: usize,
+    /// SHA-256 hashes (hex) of placeholder images served in place of missing ones
+    pub placeholder_hashes: Vec<String>,
+}
+
+impl Default for ValidatorSettings {
+    fn default() -> Self {
+        Self {
+            min_width: 1,
+            min_height: 1,
+            placeholder_hashes: Vec::new(),
+        }
+    }
+}
+
+/// Ordered validation stages a URL goes through until one stops
+#[derive(Clone)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn Validator>>,
+}
+
+impl Default for Pipeline {
+    /// Scheme and reachability checks, the classic header-based check
+    fn default() -> Self {
+        Self::new(vec![
+            Arc::new(SchemeValidator),
+            Arc::new(ReachabilityValidator),
+        ])
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new(stages: Vec<Arc<dyn Validator>>) -> Self {
+        Self { stages }
+    }
+
+    /// Pipeline of built-in stages, in the given order
+    pub fn from_kinds(kinds: &[ValidatorKind], settings: &ValidatorSettings) -> Result<Self> {
+        check_kinds(kinds)?;
+        let stages = kinds
+            .iter()
+            .map(|kind| -> Result<Arc<dyn Validator>> {
+                Ok(match kind {
+                    ValidatorKind::Scheme => Arc::new(SchemeValidator),
+                    ValidatorKind::Reachability => Arc::new(ReachabilityValidator),
+                    ValidatorKind::ContentType => Arc::new(ContentTypeValidator),
+                    ValidatorKind::MagicBytes => Arc::new(MagicBytesValidator),
+                    ValidatorKind::Dimensions => Arc::new(DimensionsValidator {
+                        min_width: settings.min_width,
+                        min_height: settings.min_height,
+                    }),
+                    ValidatorKind::PlaceholderHash => {
+                        Arc::new(PlaceholderHashValidator::new(&settings.placeholder_hashes)?)
+                    }
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::new(stages))
+    }
+
+    /// Add a stage at the end
+    pub fn with_stage(mut self, validator: impl Validator + 'static) -> Self {
+        self.stages.push(Arc::new(validator));
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|stage| stage.name())
+    }
+
+    /// Run the stages in order until one stops, returning the final result
+    pub(crate) async fn run(&self, mut inspection: Inspection<'_>) -> CheckResult {
+        for stage in &self.stages {
+            match stage.validate(&mut inspection).await {
+                Verdict::Continue => {}
+                Verdict::Stop => break,
+                Verdict::Reject(reason) => {
+                    inspection.note(|| format!("Rule: {} check failed: {}", stage.name(), reason));
+                    inspection.reject(reason);
+                    break;
+                }
+            }
+        }
+        inspection.result
+    }
+}
+
+/// Check that a list of built-in stages starts with `scheme,reachability`
+///
+/// Without them, URLs would be requested without the scheme, WAF, hotlink and
+/// signature rules, or not at all.
+pub fn check_kinds(kinds: &[ValidatorKind]) -> Result<()> {
+    if !kinds.starts_with(&[ValidatorKind::Scheme, ValidatorKind::Reachability]) {
+        return Err(anyhow!(
+            "Validation stages must start with scheme,reachability, got {}",
+            kinds
+                .iter()
+                .map(|kind| kind.to_possible_value().unwrap().get_name().to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        ));
+    }
+    Ok(())
+}
+
+/// Response to the GET request made for content stages
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Content {
+    pub status: u16,
+    pub headers: HeaderMap,
+    /// Body, or its first 5 MB
+    pub body: Vec<u8>,
+    /// Whether the whole body was read
+    pub complete: bool,
+}
+
+impl Content {
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+}
+
+/// A URL going through the pipeline: its result so far and its fetched content
+pub struct Inspection<'a> {
+    context: &'a CheckContext<'a>,
+    result: CheckResult,
+    /// Whether the reachability check accepted the URL
+    reachable: bool,
+    /// Content fetched by the first content stage (`Some(None)` if the request failed)
+    content: Option<Option<Content>>,
+}
+
+impl<'a> Inspection<'a> {
+    /// Start with a valid result; stages downgrade it
+    pub(crate) fn new(context: &'a CheckContext<'a>, id: i64, url: &str) -> Self {
+        Self {
+            context,
+            result: CheckResult {
+                id,
+                url: url.to_string(),
+                is_valid: true,
+                status_code: None,
+                error: None,
+                is_retryable: false,
+                outcome: Outcome::Valid,
+                timeout: None,
+                error_kind: None,
+                exchanges: Vec::new(),
+                annotations: BTreeMap::new(),
+            },
+            reachable: false,
+            content: None,
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.result.id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.result.url
+    }
+
+    pub fn result(&self) -> &CheckResult {
+        &self.result
+    }
+
+    pub fn result_mut(&mut self) -> &mut CheckResult {
+        &mut self.result
+    }
+
+    /// Record a finding in the result
+    pub fn annotate(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.result.annotations.insert(key.into(), value.into());
+    }
+
+    /// Record a step of the check when it is traced (`explain`)
+    pub fn note(&self, step: impl FnOnce() -> String) {
+        self.context.note(step);
+    }
+
+    /// Replace the result, keeping the annotations made so far
+    fn set_result(&mut self, mut result: CheckResult) {
+        result
+            .annotations
+            .append(&mut std::mem::take(&mut self.result.annotations));
+        self.result = result;
+    }
+
+    fn reject(&mut self, reason: String) {
+        self.result.is_valid = false;
+        self.result.outcome = Outcome::Broken;
+        self.result.error = Some(reason);
+        self.result.is_retryable = false;
+    }
+
+    /// Validate inline data URIs and refuse schemes other than HTTP(S)
+    fn check_scheme(&mut self) -> Verdict {
+        let url = self.url().to_string();
+        if data_uri::is_data_uri(&url) {
+            let validation = data_uri::validate(&url);
+            self.note(|| match &validation {
+                Ok(_) => "Rule: inline data URI holds a valid image".to_string(),
+                Err(e) => format!("Rule: inline data URI is invalid ({})", e),
+            });
+            self.result.is_valid = validation.is_ok();
+            self.result.error = validation.err();
+            self.result.outcome = Outcome::DataUri;
+            return Verdict::Stop;
+        }
+
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Verdict::Reject("Invalid URL scheme".to_string());
+        }
+        Verdict::Continue
+    }
+
+    /// Run the reachability check, returning whether it accepted the URL
+    ///
+    /// The scheme rules apply first even when a custom pipeline has no scheme
+    /// stage, so data URIs and other schemes are never requested.
+    async fn reach(&mut self) -> bool {
+        match self.check_scheme() {
+            Verdict::Continue => {}
+            Verdict::Stop => return false,
+            Verdict::Reject(reason) => {
+                self.note(|| format!("Rule: scheme check failed: {}", reason));
+                self.reject(reason);
+                return false;
+            }
+        }
+        let result = checker::check_reachability(self.context, self.id(), self.url()).await;
+        self.reachable = result.is_valid && result.outcome == Outcome::Valid;
+        self.set_result(result);
+        self.reachable
+    }
+
+    /// GET the URL once for all content stages
+    ///
+    /// Content is only fetched for URLs the reachability check accepted (it runs here
+    /// if no stage ran it before). `None` if the URL wasn't accepted or the GET didn't
+    /// return a successful response; the result then describes why, classified with
+    /// the same rules as the reachability check.
+    pub async fn content(&mut self) -> Option<&Content> {
+        if self.content.is_none() && !self.reachable && !self.reach().await {
+            self.content = Some(None);
+        }
+        if self.content.is_none() {
+            let context = self.context;
+            let (id, url) = (self.result.id, self.result.url.clone());
+            let content = match checker::send(context, Method::GET, &url, HeaderMap::new()).await {
+                Ok(response) if response.status().is_success() => {
+                    Some(read_content(response, context.timeout()).await)
+                }
+                Ok(response) => {
+                    context.note(|| {
+                        format!(
+                            "GET for content checks: HTTP {}",
+                            response.status().as_u16()
+                        )
+                    });
+                    let signature = signed_url::detect(&url);
+                    let result = checker::classify_response(
+                        context,
+                        id,
+                        &url,
+                        Method::GET,
+                        response,
+                        signature,
+                    )
+                    .await;
+                    self.set_result(result);
+                    None
+                }
+                Err(e) => {
+                    let failure = checker::request_error(context, id, &url, Method::GET, e);
+                    self.set_result(failure);
+                    None
+                }
+            };
+            if let Some(content) = &content {
+                self.result.status_code = Some(content.status);
+                self.result.timeout = Some(context.timeout());
+                context.note(|| {
+                    format!(
+                        "GET for content checks: HTTP {}, {} bytes read{}",
+                        content.status,
+                        content.body.len(),
+                        if content.complete { "" } else { " (truncated)" }
+                    )
+                });
+            }
+            self.content = Some(content);
+        }
+        self.content.as_ref().and_then(Option::as_ref)
+    }
+
+    /// Content of a successful response, or the verdict ending the pipeline
+    async fn image_content(&mut self) -> Result<&Content, Verdict> {
+        self.content().await.ok_or(Verdict::Stop)
+    }
+}
+
+/// Read a body up to `MAX_CONTENT_BYTES`, giving up after the timeout
+async fn read_content(mut response: Response, timeout: Duration) -> Content {
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let mut body = Vec::new();
+    let mut complete = false;
+    let read = async {
+        while body.len() < MAX_CONTENT_BYTES {
+            match response.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => {
+                    complete = true;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+    };
+    let _ = tokio::time::timeout(timeout, read).await;
+    body.truncate(MAX_CONTENT_BYTES);
+
+    Content {
+        status,
+        headers,
+        body,
+        complete,
+    }
+}
+
+fn is_svg(content: &Content) -> bool {
+    content
+        .content_type()
+        .is_some_and(|content_type| content_type.starts_with("image/svg+xml"))
+        && String::from_utf8_lossy(&content.body)
+            .to_ascii_lowercase()
+            .contains("<svg")
+}
+
+/// Only http(s) URLs are requested; inline data URIs are validated locally instead
+pub struct SchemeValidator;
+
+impl Validator for SchemeValidator {
+    fn name(&self) -> &str {
+        "scheme"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move { inspection.check_scheme() })
+    }
+}
+
+/// The URL answers with a usable response; any other classification ends the pipeline
+pub struct ReachabilityValidator;
+
+impl Validator for ReachabilityValidator {
+    fn name(&self) -> &str {
+        "reachability"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move {
+            if inspection.reach().await {
+                Verdict::Continue
+            } else {
+                Verdict::Stop
+            }
+        })
+    }
+}
+
+/// The body is served with an image/* Content-Type
+pub struct ContentTypeValidator;
+
+impl Validator for ContentTypeValidator {
+    fn name(&self) -> &str {
+        "content-type"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move {
+            let content_type = match inspection.image_content().await {
+                Ok(content) => content.content_type().map(str::to_string),
+                Err(verdict) => return verdict,
+            };
+            let Some(content_type) = content_type else {
+                return Verdict::Reject("No Content-Type".to_string());
+            };
+            inspection.annotate("content_type", content_type.as_str());
+            if content_type
+                .trim()
+                .to_ascii_lowercase()
+                .starts_with("image/")
+            {
+                Verdict::Continue
+            } else {
+                Verdict::Reject(format!("Content-Type {} is not an image", content_type))
+            }
+        })
+    }
+}
+
+/// The body starts with the signature of a known image format
+pub struct MagicBytesValidator;
+
+impl Validator for MagicBytesValidator {
+    fn name(&self) -> &str {
+        "magic-bytes"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move {
+            let format = match inspection.image_content().await {
+                Ok(content) if is_svg(content) => Ok("svg".to_string()),
+                Ok(content) => imagesize::image_type(&content.body)
+                    .map(|image_type| format!("{:?}", image_type).to_ascii_lowercase())
+                    .map_err(|_| trace::sniff(&content.body)),
+                Err(verdict) => return verdict,
+            };
+            match format {
+                Ok(format) => {
+                    inspection.annotate("format", format);
+                    Verdict::Continue
+                }
+                Err(sniffed) => Verdict::Reject(format!("Body is not an image ({})", sniffed)),
+            }
+        })
+    }
+}
+
+/// The image is at least the minimum size (SVGs scale, so they always pass)
+pub struct DimensionsValidator {
+    pub min_width: usize,
+    pub min_height: usize,
+}
+
+impl Validator for DimensionsValidator {
+    fn name(&self) -> &str {
+        "dimensions"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move {
+            let size = match inspection.image_content().await {
+                Ok(content) if is_svg(content) => return Verdict::Continue,
+                Ok(content) => imagesize::blob_size(&content.body).ok(),
+                Err(verdict) => return verdict,
+            };
+            let Some(size) = size else {
+                return Verdict::Reject("Image dimensions could not be read".to_string());
+            };
+            inspection.annotate("dimensions", format!("{}x{}", size.width, size.height));
+            if size.width < self.min_width || size.height < self.min_height {
+                return Verdict::Reject(format!(
+                    "Image is {}x{}, smaller than {}x{}",
+                    size.width, size.height, self.min_width, self.min_height
+                ));
+            }
+            Verdict::Continue
+        })
+    }
+}
+
+/// The body is not one of the placeholder images suppliers serve for missing ones
+pub struct PlaceholderHashValidator {
+    hashes: HashSet<String>,
+}
+
+impl PlaceholderHashValidator {
+    /// Hashes are hex SHA-256 digests of whole bodies
+    pub fn new(hashes: &[String]) -> Result<Self> {
+        if hashes.is_empty() {
+            return Err(anyhow!(
+                "The placeholder-hash validator needs at least one --placeholder-hash"
+            ));
+        }
+        let hashes = hashes
+            .iter()
+            .map(|hash| {
+                let hash = hash.trim().to_ascii_lowercase();
+                if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(anyhow!("Invalid SHA-256 placeholder hash: {}", hash));
+                }
+                Ok(hash)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { hashes })
+    }
+}
+
+impl Validator for PlaceholderHashValidator {
+    fn name(&self) -> &str {
+        "placeholder-hash"
+    }
+
+    fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+        Box::pin(async move {
+            let hash = match inspection.image_content().await {
+                // Placeholders are small; a body too large to read whole isn't one
+                Ok(content) if !content.complete => return Verdict::Continue,
+                Ok(content) => sha256_hex(&content.body),
+                Err(verdict) => return verdict,
+            };
+            let is_placeholder = self.hashes.contains(&hash);
+            inspection.annotate("sha256", hash);
+            if is_placeholder {
+                Verdict::Reject("Body is a known placeholder image".to_string())
+            } else {
+                Verdict::Continue
+            }
+        })
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+
+    /// Stage rejecting every URL whose path contains a word
+    struct Forbidden(&'static str);
+
+    impl Validator for Forbidden {
+        fn name(&self) -> &str {
+            "forbidden-word"
+        }
+
+        fn validate<'a>(&'a self, inspection: &'a mut Inspection<'_>) -> BoxFuture<'a, Verdict> {
+            Box::pin(async move {
+                inspection.annotate("checked_by", "forbidden-word");
+                if inspection.url().contains(self.0) {
+                    Verdict::Reject(format!("URL contains {}", self.0))
+                } else {
+                    Verdict::Continue
+                }
+            })
+        }
+    }
+
+    async fn check(pipeline: Pipeline, server: &MockServer, path: &str) -> CheckResult {
+        let checker = server
+            .checker_builder()
+            .unwrap()
+            .pipeline(pipeline)
+            .build()
+            .unwrap();
+        checker
+            .check_batch(vec![(1, server.url(path))])
+            .await
+            .remove(0)
+    }
+
+    /// Built-in stages after the mandatory scheme and reachability stages
+    fn kinds(content: &[ValidatorKind]) -> Vec<ValidatorKind> {
+        [ValidatorKind::Scheme, ValidatorKind::Reachability]
+            .iter()
+            .chain(content)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_from_kinds() {
+        let pipeline = Pipeline::from_kinds(
+            &kinds(&[ValidatorKind::Dimensions]),
+            &ValidatorSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            pipeline.names().collect::<Vec<_>>(),
+            ["scheme", "reachability", "dimensions"]
+        );
+
+        // Content stages never run without the scheme and reachability stages first
+        for invalid in [
+            &[][..],
+            &[ValidatorKind::ContentType],
+            &[ValidatorKind::Reachability, ValidatorKind::MagicBytes],
+            &[ValidatorKind::Reachability, ValidatorKind::Scheme],
+        ] {
+            let error = Pipeline::from_kinds(invalid, &ValidatorSettings::default()).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("Validation stages must start"));
+        }
+
+        // Placeholder hashes are required and must be SHA-256 digests
+        let placeholder = kinds(&[ValidatorKind::PlaceholderHash]);
+        assert!(Pipeline::from_kinds(&placeholder, &ValidatorSettings::default()).is_err());
+        let settings = ValidatorSettings {
+            placeholder_hashes: vec!["abc".to_string()],
+            ..Default::default()
+        };
+        assert!(Pipeline::from_kinds(&placeholder, &settings).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_content_stages() {
+        let server = MockServer::start().await.unwrap();
+        let content = Pipeline::from_kinds(
+            &kinds(&[
+                ValidatorKind::ContentType,
+                ValidatorKind::MagicBytes,
+                ValidatorKind::Dimensions,
+            ]),
+            &ValidatorSettings::default(),
+        )
+        .unwrap();
+
+        let result = check(content.clone(), &server, "/image.png").await;
+        assert_eq!(result.outcome, Outcome::Valid);
+        assert_eq!(result.annotations["format"], "png");
+        assert_eq!(result.annotations["dimensions"], "1x1");
+        assert_eq!(result.annotations["content_type"], "image/png");
+
+        // The soft 404 passes the header-based check but not the content stages
+        let result = check(content.clone(), &server, "/soft-404.png").await;
+        assert_eq!(result.outcome, Outcome::Broken);
+        assert!(result.error.unwrap().contains("not an image"));
+
+        // Reachability still short-circuits before any content is fetched
+        let result = check(content, &server, "/missing.jpg").await;
+        assert_eq!(result.status_code, Some(404));
+        assert!(result.annotations.is_empty());
+
+        // Too small, or a known placeholder
+        let settings = ValidatorSettings {
+            min_width: 2,
+            min_height: 2,
+            placeholder_hashes: vec![sha256_hex(b"placeholder")],
+        };
+        let dimensions =
+            Pipeline::from_kinds(&kinds(&[ValidatorKind::Dimensions]), &settings).unwrap();
+        let result = check(dimensions, &server, "/image.png").await;
+        assert_eq!(
+            result.error.as_deref(),
+            Some("Image is 1x1, smaller than 2x2")
+        );
+
+        let placeholder =
+            Pipeline::from_kinds(&kinds(&[ValidatorKind::PlaceholderHash]), &settings).unwrap();
+        let result = check(placeholder, &server, "/image.png").await;
+        assert!(result.is_valid);
+        assert_eq!(result.annotations["sha256"].len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_block_page_before_content_stages() {
+        let server = MockServer::start().await.unwrap();
+        let settings = ValidatorSettings::default();
+
+        // A challenge page is blocked, not a broken image, whatever stages follow
+        let magic_bytes =
+            Pipeline::from_kinds(&kinds(&[ValidatorKind::MagicBytes]), &settings).unwrap();
+        let result = check(magic_bytes, &server, "/challenge.png").await;
+        assert_eq!(result.outcome, Outcome::Blocked);
+        assert!(!result.is_retryable);
+
+        // Custom pipelines get the same rules from their reachability stage
+        let reachability_first = Pipeline::new(vec![
+            Arc::new(ReachabilityValidator),
+            Arc::new(MagicBytesValidator),
+        ]);
+        let result = check(reachability_first, &server, "/challenge.png").await;
+        assert_eq!(result.outcome, Outcome::Blocked);
+    }
+
+    #[tokio::test]
+    async fn test_reachability_applies_scheme_rules() {
+        let server = MockServer::start().await.unwrap();
+        let checker = server
+            .checker_builder()
+            .unwrap()
+            .pipeline(Pipeline::new(vec![Arc::new(MagicBytesValidator)]))
+            .build()
+            .unwrap();
+
+        // Without a scheme stage, data URIs and other schemes are still never requested
+        let results = checker
+            .check_batch(vec![
+                (1, "data:image/png;base64,AAAA".to_string()),
+                (2, "ftp://example.com/a.png".to_string()),
+            ])
+            .await;
+        assert_eq!(results[0].outcome, Outcome::DataUri);
+        assert!(!results[0].is_valid);
+        assert_eq!(results[1].outcome, Outcome::Broken);
+        assert_eq!(results[1].error.as_deref(), Some("Invalid URL scheme"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_stage() {
+        let server = MockServer::start().await.unwrap();
+        let pipeline = Pipeline::default().with_stage(Forbidden("image"));
+
+        let result = check(pipeline.clone(), &server, "/image.png").await;
+        assert_eq!(result.error.as_deref(), Some("URL contains image"));
+        assert_eq!(result.annotations["checked_by"], "forbidden-word");
+
+        // Earlier stages stop the pipeline before the custom stage runs
+        let result = check(pipeline, &server, "/missing.jpg").await;
+        assert_eq!(result.status_code, Some(404));
+        assert!(result.annotations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scheme_stage() {
+        let server = MockServer::start().await.unwrap();
+        let result = check(Pipeline::default(), &server, "/image.png").await;
+        assert!(result.is_valid);
+
+        let checker = server.checker().unwrap();
+        let results = checker
+            .check_batch(vec![
+                (1, "ftp://img.example.com/a.jpg".to_string()),
+                (2, "data:text/plain,hello".to_string()),
+            ])
+            .await;
+        let find = |id| results.iter().find(|result| result.id == id).unwrap();
+        assert_eq!(find(1).error.as_deref(), Some("Invalid URL scheme"));
+        assert_eq!(find(2).outcome, Outcome::DataUri);
+        assert!(!find(2).is_valid);
+    }
+}
@@ -0,0 +1,165 @@
+use reqwest::header::{HeaderMap, CONTENT_TYPE, SERVER};
+use reqwest::StatusCode;
+
+/// Status codes WAFs and bot managers use for challenge and block pages
+const CHALLENGE_STATUS_CODES: [u16; 3] = [
+    403, // Forbidden
+    429, // Too Many Requests
+    503, // Service Unavailable
+];
+
+/// Detect a WAF or bot-challenge response from its status, headers and body start
+///
+/// Returns the name of the protection service. Explicit challenge headers are enough,
+/// so HEAD responses can be classified from them alone. An HTML page served through a
+/// protection layer only counts if the body carries one of its block-page markers:
+/// origin errors (a 503 during an outage) pass through the same layer.
+pub fn detect(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Option<&'static str> {
+    if !CHALLENGE_STATUS_CODES.contains(&status.as_u16()) {
+        return None;
+    }
+
+    // Explicit challenge markers, whatever the body looks like
+    if headers.contains_key("cf-mitigated") {
+        return Some("Cloudflare");
+    }
+    if header(headers, "x-amzn-waf-action").is_some_and(|a| a == "challenge" || a == "captcha") {
+        return Some("AWS WAF");
+    }
+    if headers.contains_key("x-datadome") {
+        return Some("DataDome");
+    }
+
+    let service = page_service(status, headers)?;
+    let body = String::from_utf8_lossy(body).to_ascii_lowercase();
+    block_page_markers(service)
+        .iter()
+        .any(|marker| body.contains(marker))
+        .then_some(service)
+}
+
+/// Protection layer that served an HTML error page, which is a block page only if its
+/// body says so (see [`detect`])
+pub fn page_service(status: StatusCode, headers: &HeaderMap) -> Option<&'static str> {
+    if !CHALLENGE_STATUS_CODES.contains(&status.as_u16()) {
+        return None;
+    }
+    if !header(headers, CONTENT_TYPE.as_str()).is_some_and(|ct| ct.contains("text/html")) {
+        return None;
+    }
+
+    let server = header(headers, SERVER.as_str()).unwrap_or_default();
+    if server.contains("cloudflare") || headers.contains_key("cf-ray") {
+        return Some("Cloudflare");
+    }
+    if server.contains("akamaighost") || headers.contains_key("akamai-grn") {
+        return Some("Akamai");
+    }
+    if headers.contains_key("x-sucuri-id") {
+        return Some("Sucuri");
+    }
+    if headers.contains_key("x-iinfo")
+        || header(headers, "x-cdn").is_some_and(|cdn| cdn.contains("incapsula"))
+    {
+        return Some("Imperva");
+    }
+    None
+}
+
+/// Lowercase snippets of the challenge and block pages of a service
+fn block_page_markers(service: &str) -> &'static [&'static str] {
+    match service {
+        "Cloudflare" => &[
+            "cf-chl",
+            "challenge-platform",
+            "attention required! | cloudflare",
+            "just a moment...",
+        ],
+        "Akamai" => &["errors.edgesuite.net", "reference&#32;&#35;"],
+        "Sucuri" => &["sucuri website firewall"],
+        "Imperva" => &["incapsula incident id", "_incapsula_resource"],
+        _ => &[],
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_detect_cloudflare() {
+        let challenge = headers(&[("cf-mitigated", "challenge"), ("server", "cloudflare")]);
+        assert_eq!(
+            detect(StatusCode::FORBIDDEN, &challenge, b""),
+            Some("Cloudflare")
+        );
+
+        let block_page = headers(&[("server", "cloudflare"), ("content-type", "text/html")]);
+        let body = b"<title>Attention Required! | Cloudflare</title>";
+        assert_eq!(
+            detect(StatusCode::FORBIDDEN, &block_page, body),
+            Some("Cloudflare")
+        );
+        assert_eq!(
+            page_service(StatusCode::FORBIDDEN, &block_page),
+            Some("Cloudflare")
+        );
+    }
+
+    #[test]
+    fn test_detect_akamai() {
+        let block_page = headers(&[("server", "AkamaiGHost"), ("content-type", "text/html")]);
+        let body = b"<H1>Access Denied</H1> Reference&#32;&#35;18&#46;2d1c";
+        assert_eq!(
+            detect(StatusCode::FORBIDDEN, &block_page, body),
+            Some("Akamai")
+        );
+    }
+
+    #[test]
+    fn test_plain_errors_are_not_challenges() {
+        // Images behind Cloudflare that are genuinely missing
+        let not_found = headers(&[("server", "cloudflare"), ("content-type", "text/html")]);
+        assert_eq!(detect(StatusCode::NOT_FOUND, &not_found, b""), None);
+
+        // An origin outage behind Cloudflare, with its own HTML error page
+        let outage = headers(&[
+            ("server", "cloudflare"),
+            ("cf-ray", "8a1b2c3d4e5f6789-AMS"),
+            ("content-type", "text/html; charset=utf-8"),
+        ]);
+        let body = b"<html><h1>Service temporarily unavailable</h1></html>";
+        assert_eq!(detect(StatusCode::SERVICE_UNAVAILABLE, &outage, body), None);
+
+        // Cloudflare's own origin error pages (522 and friends) share markup with block pages
+        let body = b"<div id=\"cf-error-details\"><h1>Connection timed out</h1> \
+                     <span class=\"cf-error-code\">522</span></div>";
+        for status in [522, 503] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert_eq!(detect(status, &outage, body), None);
+        }
+
+        // A CDN 403 without an HTML block page
+        let forbidden = headers(&[
+            ("server", "cloudflare"),
+            ("content-type", "application/xml"),
+        ]);
+        assert_eq!(detect(StatusCode::FORBIDDEN, &forbidden, b""), None);
+    }
+}